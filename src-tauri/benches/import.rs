@@ -0,0 +1,92 @@
+// Whole-dictionary import through `cjdic --import`, the path that prepared
+// statements, the parallel term bank parse and digest hex encoding speed up.
+// Run with `cargo bench`; pass `-- --save-baseline <name>` before a change
+// and `-- --baseline <name>` after it to compare.
+
+use std::fs;
+use std::path::Path;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use serde_json::json;
+
+const TERMS_PER_BANK: usize = 10_000;
+/// Banks of the default import bench.
+const BANKS: usize = 3;
+/// Banks of the parse bench, about as many terms as JMdict.
+const JMDICT_BANKS: usize = 30;
+
+/// A format 3 dictionary of `banks` term banks with distinct glossaries, so
+/// every one is hashed and interned.
+fn write_dictionary(dir: &Path, banks: usize) {
+    let index = json!({ "title": "Bench", "revision": "1", "format": 3 });
+    fs::write(dir.join("index.json"), index.to_string()).unwrap();
+    for bank in 0..banks {
+        let terms: Vec<_> = (0..TERMS_PER_BANK)
+            .map(|i| {
+                let n = bank * TERMS_PER_BANK + i;
+                json!([format!("語{n}"), format!("ご{n}"), "n", if n.is_multiple_of(3) { "v5 vt" } else { "" }, n % 100, [format!("word number {n}")], n, ""])
+            })
+            .collect();
+        fs::write(dir.join(format!("term_bank_{}.json", bank + 1)), serde_json::to_string(&terms).unwrap()).unwrap();
+    }
+}
+
+/// Import `dict` into a new database of its own, on a rayon pool of `threads`
+/// when given. One thread takes the serial parse, more the parallel one.
+fn bench_import(c: &mut Criterion, group: &str, banks: usize, threads: &[(&str, Option<usize>)]) {
+    let dict = tempfile::tempdir().unwrap();
+    write_dictionary(dict.path(), banks);
+    let mut group = c.benchmark_group(group);
+    group.sample_size(10);
+    for &(name, threads) in threads {
+        let pool = threads.map(|n| rayon::ThreadPoolBuilder::new().num_threads(n).build().unwrap());
+        let id = match name {
+            "" => format!("{} terms", banks * TERMS_PER_BANK),
+            name => format!("{} terms {name}", banks * TERMS_PER_BANK),
+        };
+        group.bench_function(id, |b| {
+            b.iter_batched(
+                || tempfile::tempdir().unwrap(),
+                |db_dir| {
+                    let db = db_dir.path().join("bench.db");
+                    let args = ["--db".to_string(), db.display().to_string(), dict.path().display().to_string()];
+                    let import = || cjdic_lib::import_headless(&args).unwrap();
+                    match &pool {
+                        Some(pool) => pool.install(import),
+                        None => import(),
+                    }
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn import(c: &mut Criterion) {
+    bench_import(c, "import", BANKS, &[("", None)]);
+}
+
+/// The serial parse against the parallel one, which needs more than one core
+/// to come out ahead.
+fn parse(c: &mut Criterion) {
+    let cores = std::thread::available_parallelism().map_or(2, |n| n.get().max(2));
+    bench_import(c, "parse", JMDICT_BANKS, &[("serial", Some(1)), ("parallel", Some(cores))]);
+}
+
+/// Glossary hashing, the digest hex encoding included, on its own: in a
+/// whole import it is lost in the noise.
+fn digest(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sha1_hex");
+    let plain = json!(["word number 1"]).to_string();
+    let senses = json!((0..8).map(|i| format!("sense {i} of a fairly ordinary entry")).collect::<Vec<_>>()).to_string();
+    let structured = json!([{ "type": "structured-content", "content": (0..40).map(|i| json!({ "tag": "li", "content": format!("item {i}") })).collect::<Vec<_>>() }]).to_string();
+    for (name, glossary) in [("plain", &plain), ("senses", &senses), ("structured", &structured)] {
+        group.throughput(Throughput::Bytes(glossary.len() as u64));
+        group.bench_function(format!("{name} {} bytes", glossary.len()), |b| b.iter(|| cjdic_lib::sha1_hex(black_box(glossary))));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, import, parse, digest);
+criterion_main!(benches);
@@ -0,0 +1,202 @@
+use serde_json::Value;
+
+/// Structured-content tags that render as their own block.
+const BLOCK_TAGS: &[&str] = &[
+    "div", "p", "li", "ol", "ul", "table", "thead", "tbody", "tfoot", "tr", "details", "summary",
+];
+
+fn break_line(out: &mut String) {
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+fn walk(v: &Value, out: &mut String) {
+    match v {
+        Value::String(s) => out.push_str(s),
+        Value::Array(nodes) => nodes.iter().for_each(|n| walk(n, out)),
+        Value::Object(node) => {
+            let tag = node.get("tag").and_then(Value::as_str).unwrap_or("");
+            match tag {
+                // An image's text is its `alt`, if it has one
+                "img" => {
+                    if let Some(alt) = node.get("alt").and_then(Value::as_str) {
+                        out.push_str(alt);
+                    }
+                }
+                // Ruby readings would run into the base text
+                "rt" | "rp" => {}
+                "br" => out.push('\n'),
+                _ if BLOCK_TAGS.contains(&tag) => {
+                    break_line(out);
+                    if let Some(content) = node.get("content") {
+                        walk(content, out);
+                    }
+                    break_line(out);
+                }
+                // Inline tags, `a` included: keep the text, drop attributes like `href`
+                _ => {
+                    if let Some(content) = node.get("content") {
+                        walk(content, out);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Plain text of a Yomitan structured-content node tree, with block tags on
+/// lines of their own.
+pub fn structured_content_text(v: &Value) -> String {
+    let mut out = String::new();
+    walk(v, &mut out);
+    out.trim().to_string()
+}
+
+/// Plain text of a canonical glossary, one definition per line, for search.
+pub fn glossary_text(v: &Value) -> String {
+    let defs = match v {
+        Value::Array(defs) => defs.as_slice(),
+        other => std::slice::from_ref(other),
+    };
+    let mut out = String::new();
+    for def in defs {
+        let text = match def {
+            Value::String(s) => s.clone(),
+            Value::Object(o) => match o.get("type").and_then(Value::as_str) {
+                Some("text") => o.get("text").and_then(Value::as_str).unwrap_or_default().to_string(),
+                Some("structured-content") => o.get("content").map(structured_content_text).unwrap_or_default(),
+                _ => String::new(),
+            },
+            _ => String::new(),
+        };
+        if !text.is_empty() {
+            break_line(&mut out);
+            out.push_str(&text);
+        }
+    }
+    out
+}
+
+fn collect_media(v: &Value, out: &mut Vec<String>) {
+    match v {
+        Value::Array(nodes) => nodes.iter().for_each(|n| collect_media(n, out)),
+        Value::Object(node) => {
+            let image = node.get("tag").and_then(Value::as_str) == Some("img")
+                || node.get("type").and_then(Value::as_str) == Some("image");
+            if let Some(path) = node.get("path").and_then(Value::as_str).filter(|_| image)
+                && !out.iter().any(|p| p == path)
+            {
+                out.push(path.to_string());
+            }
+            if let Some(content) = node.get("content") {
+                collect_media(content, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Paths of the files a canonical glossary's image definitions and
+/// structured-content `img` tags show, each once.
+pub fn media_paths(v: &Value) -> Vec<String> {
+    let mut out = Vec::new();
+    collect_media(v, &mut out);
+    out
+}
+
+/// How a canonical glossary renders, for the frontend to branch on:
+/// `structured` if any definition is structured content, else `image` if
+/// any is an image, else `text`.
+pub fn glossary_content_type(v: &Value) -> &'static str {
+    let defs = match v {
+        Value::Array(defs) => defs.as_slice(),
+        other => std::slice::from_ref(other),
+    };
+    let types: Vec<&str> = defs.iter().filter_map(|d| d.get("type").and_then(Value::as_str)).collect();
+    if types.contains(&"structured-content") {
+        "structured"
+    } else if types.contains(&"image") {
+        "image"
+    } else {
+        "text"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn node_shapes() {
+        assert_eq!(structured_content_text(&json!("plain")), "plain");
+        assert_eq!(structured_content_text(&json!(["a", "b", ["c"]])), "abc");
+        assert_eq!(structured_content_text(&json!({ "tag": "span", "content": "inline" })), "inline");
+        assert_eq!(structured_content_text(&json!({ "tag": "span" })), "");
+        assert_eq!(structured_content_text(&json!(42)), "");
+    }
+
+    #[test]
+    fn nested_lists_break_lines() {
+        // JMdict's sense list, a gloss list inside each sense
+        let senses = json!({
+            "tag": "ol",
+            "content": [
+                { "tag": "li", "content": [
+                    { "tag": "span", "data": { "content": "partOfSpeech" }, "content": "godan verb" },
+                    { "tag": "ul", "data": { "content": "glossary" }, "content": [
+                        { "tag": "li", "content": "to read" },
+                        { "tag": "li", "content": "to recite" },
+                    ] },
+                ] },
+                { "tag": "li", "content": { "tag": "ul", "content": { "tag": "li", "content": "to guess" } } },
+            ],
+        });
+        assert_eq!(structured_content_text(&senses), "godan verb\nto read\nto recite\nto guess");
+    }
+
+    #[test]
+    fn ruby_keeps_base_text_only() {
+        let example = json!({
+            "tag": "div",
+            "content": [
+                { "tag": "ruby", "content": ["本", { "tag": "rp", "content": "(" }, { "tag": "rt", "content": "ほん" }, { "tag": "rp", "content": ")" }] },
+                "を",
+                { "tag": "ruby", "content": ["読", { "tag": "rt", "content": "よ" }] },
+                "む",
+            ],
+        });
+        assert_eq!(structured_content_text(&example), "本を読む");
+    }
+
+    #[test]
+    fn images_give_alt_text() {
+        let content = json!([
+            { "tag": "img", "path": "img/arrow.png", "alt": "→" },
+            "see also",
+            { "tag": "img", "path": "img/icon.png" },
+        ]);
+        assert_eq!(structured_content_text(&content), "→see also");
+        assert_eq!(media_paths(&json!([{ "type": "structured-content", "content": content }])), ["img/arrow.png", "img/icon.png"]);
+    }
+
+    #[test]
+    fn br_breaks_inside_inline_text() {
+        let content = json!({ "tag": "span", "content": ["first", { "tag": "br" }, "second"] });
+        assert_eq!(structured_content_text(&content), "first\nsecond");
+    }
+
+    #[test]
+    fn glossary_text_joins_definitions() {
+        let glossary = json!([
+            "plain",
+            { "type": "text", "text": "typed" },
+            { "type": "structured-content", "content": { "tag": "div", "content": "structured" } },
+            { "type": "image", "path": "img/x.png" },
+        ]);
+        assert_eq!(glossary_text(&glossary), "plain\ntyped\nstructured");
+        assert_eq!(glossary_content_type(&glossary), "structured");
+    }
+}
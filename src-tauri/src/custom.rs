@@ -0,0 +1,119 @@
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde_json::Value;
+
+use crate::kana::{fold_latin, normalize};
+use crate::yomitan_import::GlossaryRow;
+
+/// The dictionary holding user-added terms, created on first use.
+fn user_dict_id(tx: &Transaction) -> rusqlite::Result<i64> {
+    if let Some(id) = tx
+        .query_row("SELECT id FROM dictionaries WHERE is_custom = 1", [], |r| r.get(0))
+        .optional()?
+    {
+        return Ok(id);
+    }
+    // An imported dictionary may already be called `User`: take the first revision it leaves free
+    for n in 0.. {
+        let revision = if n == 0 { "user".to_string() } else { format!("user-{}", n) };
+        let inserted = tx.execute(
+            "INSERT OR IGNORE INTO dictionaries (title, revision, description, is_bundled, is_custom) VALUES ('User', ?1, 'Terms you added yourself', 0, 1)",
+            params![revision],
+        )?;
+        if inserted > 0 {
+            break;
+        }
+    }
+    Ok(tx.last_insert_rowid())
+}
+
+/// Add a term with a plain-text definition to the user dictionary. Returns the new term's id.
+pub fn add_term(conn: &Connection, term: &str, reading: &str, definition: &str) -> anyhow::Result<i64> {
+    let (term, reading, definition) = (normalize(term.trim()), normalize(reading.trim()), definition.trim());
+    if term.is_empty() || definition.is_empty() {
+        anyhow::bail!("a custom term needs both a term and a definition");
+    }
+    let glossary = GlossaryRow::new(Value::Array(vec![Value::String(definition.to_string())]), true)?;
+
+    let tx = conn.unchecked_transaction()?;
+    let dict_id = user_dict_id(&tx)?;
+    let inserted = tx.execute(
+        "INSERT OR IGNORE INTO glossaries (hash, content, content_type) VALUES (?1, ?2, ?3)",
+        params![glossary.hash, glossary.json, glossary.content_type],
+    )?;
+    let glossary_id: i64 = tx.query_row("SELECT id FROM glossaries WHERE hash = ?1", params![glossary.hash], |r| r.get(0))?;
+    if inserted > 0 {
+        tx.execute("INSERT INTO glossary_fts (rowid, text) VALUES (?1, ?2)", params![glossary_id, glossary.text])?;
+    }
+    tx.execute(
+        "INSERT INTO terms (dict_id, term, term_norm, reading, score, glossary_id) VALUES (?1, ?2, NULLIF(?3, ?2), ?4, 0, ?5)",
+        params![dict_id, term, fold_latin(&term), reading, glossary_id],
+    )?;
+    let id = tx.last_insert_rowid();
+    tx.commit()?;
+    Ok(id)
+}
+
+/// Remove a term added with `add_term`, and its glossary unless another term
+/// shares it. Terms of imported dictionaries are refused.
+pub fn remove_term(conn: &Connection, id: i64) -> anyhow::Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    let glossary_id: Option<i64> = tx
+        .query_row(
+            "SELECT glossary_id FROM terms WHERE id = ?1 AND dict_id IN (SELECT id FROM dictionaries WHERE is_custom = 1)",
+            params![id],
+            |r| r.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| anyhow::anyhow!("no custom term with id {}", id))?;
+    tx.execute("DELETE FROM terms WHERE id = ?1", params![id])?;
+    // The user's terms carry no tag or rule sets, so their glossary is all there is to collect
+    if let Some(glossary_id) = glossary_id
+        && tx.execute(
+            "DELETE FROM glossaries WHERE id = ?1 AND NOT EXISTS (SELECT 1 FROM terms WHERE glossary_id = ?1)",
+            params![glossary_id],
+        )? > 0
+    {
+        tx.execute("DELETE FROM glossary_fts WHERE rowid = ?1", params![glossary_id])?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yomitan_import::create_schema;
+
+    fn count(conn: &Connection, sql: &str) -> i64 {
+        conn.query_row(sql, [], |r| r.get(0)).unwrap()
+    }
+
+    #[test]
+    fn user_dictionary_sidesteps_an_imported_user() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_schema(&conn).unwrap();
+        conn.execute("INSERT INTO dictionaries (title, revision) VALUES ('User', 'user')", []).unwrap();
+        add_term(&conn, "猫", "ねこ", "cat").unwrap();
+        add_term(&conn, "犬", "いぬ", "dog").unwrap();
+        let revision: String = conn.query_row("SELECT revision FROM dictionaries WHERE is_custom = 1", [], |r| r.get(0)).unwrap();
+        assert_eq!(revision, "user-1");
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM dictionaries"), 2);
+    }
+
+    #[test]
+    fn removing_a_term_keeps_a_shared_glossary() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_schema(&conn).unwrap();
+        let first = add_term(&conn, "猫", "ねこ", "cat").unwrap();
+        let second = add_term(&conn, "ネコ", "ねこ", "cat").unwrap();
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM glossaries"), 1);
+
+        remove_term(&conn, first).unwrap();
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM glossaries"), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM glossary_fts"), 1);
+        remove_term(&conn, second).unwrap();
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM glossaries"), 0);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM glossary_fts"), 0);
+        assert!(remove_term(&conn, second).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Deserialize;
+
+/// Yomichan-style deinflection table: reason -> suffix rewrites.
+const DEINFLECT_JSON: &str = include_str!("../resources/deinflect.json");
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawVariant {
+    kana_in: String,
+    kana_out: String,
+    rules_in: Vec<String>,
+    rules_out: Vec<String>,
+}
+
+struct Variant {
+    kana_in: String,
+    kana_out: String,
+    rules_in: u32,
+    rules_out: u32,
+}
+
+/// A candidate base form reached by undoing `reasons`, listed from the one
+/// nearest the base form to the outermost suffix as Yomitan shows them.
+pub struct Deinflection {
+    pub term: String,
+    /// Word classes the base form may have: the `rulesOut` of the last rewrite
+    /// applied, or 0 for the word as given.
+    pub rules: u32,
+    pub reasons: Vec<String>,
+}
+
+impl Deinflection {
+    /// Whether a dictionary entry tagged with the space-separated `rules` can be
+    /// this base form, as Yomitan matches `rulesOut` against a term's rules: a
+    /// godan entry never accepts a base reached by an ichidan rewrite, and an
+    /// entry with no known word class only matches the word as given.
+    pub fn accepts(&self, rules: &str) -> bool {
+        self.rules == 0 || rule_flags(rules.split_whitespace()) & self.rules != 0
+    }
+}
+
+fn rule_flags<S: AsRef<str>>(names: impl IntoIterator<Item = S>) -> u32 {
+    names.into_iter().fold(0, |acc, name| {
+        acc | match name.as_ref() {
+            "v1" => 1 << 0,
+            "v5" => 1 << 1,
+            "vs" => 1 << 2,
+            "vk" => 1 << 3,
+            "vz" => 1 << 4,
+            "adj-i" => 1 << 5,
+            "iru" => 1 << 6,
+            _ => 0,
+        }
+    })
+}
+
+pub struct Deinflector {
+    reasons: Vec<(String, Vec<Variant>)>,
+}
+
+impl Deinflector {
+    pub fn new() -> serde_json::Result<Self> {
+        let raw: BTreeMap<String, Vec<RawVariant>> = serde_json::from_str(DEINFLECT_JSON)?;
+        let reasons = raw
+            .into_iter()
+            .map(|(reason, variants)| {
+                let variants = variants
+                    .into_iter()
+                    .map(|v| Variant {
+                        kana_in: v.kana_in,
+                        kana_out: v.kana_out,
+                        rules_in: rule_flags(&v.rules_in),
+                        rules_out: rule_flags(&v.rules_out),
+                    })
+                    .collect();
+                (reason, variants)
+            })
+            .collect();
+        Ok(Deinflector { reasons })
+    }
+
+    /// All base forms `word` may have been inflected from, including `word`
+    /// itself, each reached by a given chain of reasons once.
+    pub fn deinflect(&self, word: &str) -> Vec<Deinflection> {
+        let mut results = vec![Deinflection {
+            term: word.to_string(),
+            rules: 0,
+            reasons: vec![],
+        }];
+
+        let mut i = 0;
+        while i < results.len() {
+            let term = results[i].term.clone();
+            let rules = results[i].rules;
+            for (reason, variants) in &self.reasons {
+                for v in variants {
+                    if (rules != 0 && rules & v.rules_in == 0)
+                        || !term.ends_with(&v.kana_in)
+                        || term.len() - v.kana_in.len() + v.kana_out.len() == 0
+                    {
+                        continue;
+                    }
+                    let base = format!("{}{}", &term[..term.len() - v.kana_in.len()], v.kana_out);
+                    let mut chain = vec![reason.clone()];
+                    chain.extend(results[i].reasons.iter().cloned());
+                    results.push(Deinflection {
+                        term: base,
+                        rules: v.rules_out,
+                        reasons: chain,
+                    });
+                }
+            }
+            i += 1;
+        }
+
+        // Rewrites reaching the same base by the same reasons, e.g. 来なかった
+        // to 来る as vk and as v1, are one candidate of either word class
+        let mut merged: Vec<Deinflection> = Vec::with_capacity(results.len());
+        let mut index: HashMap<(String, Vec<String>), usize> = HashMap::new();
+        for d in results {
+            match index.get(&(d.term.clone(), d.reasons.clone())) {
+                Some(&at) => merged[at].rules |= d.rules,
+                None => {
+                    index.insert((d.term.clone(), d.reasons.clone()), merged.len());
+                    merged.push(d);
+                }
+            }
+        }
+        merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The reasons of every candidate `word` deinflects to `term`.
+    fn reasons_to(word: &str, term: &str) -> Vec<Vec<String>> {
+        Deinflector::new().unwrap().deinflect(word).into_iter().filter(|d| d.term == term).map(|d| d.reasons).collect()
+    }
+
+    #[test]
+    fn word_as_given_comes_first() {
+        let deinflector = Deinflector::new().unwrap();
+        for word in ["食べた", "読んだ", "本"] {
+            let first = &deinflector.deinflect(word)[0];
+            assert_eq!(first.term, word);
+            assert_eq!(first.rules, 0);
+            assert!(first.reasons.is_empty());
+        }
+    }
+
+    #[test]
+    fn undoes_past_and_chained_suffixes() {
+        assert_eq!(reasons_to("食べた", "食べる"), [["past"]]);
+        assert_eq!(reasons_to("読んだ", "読む"), [["past"]]);
+        assert!(reasons_to("食べさせられた", "食べる").contains(&vec!["causative".to_string(), "potential or passive".to_string(), "past".to_string()]));
+    }
+
+    #[test]
+    fn candidates_filter_by_word_class() {
+        let deinflector = Deinflector::new().unwrap();
+        let candidates = deinflector.deinflect("食べた");
+        let taberu = candidates.iter().find(|d| d.term == "食べる").unwrap();
+        assert!(taberu.accepts("v1"));
+        assert!(taberu.accepts("v1 vt"));
+        assert!(!taberu.accepts("v5"));
+        assert!(!taberu.accepts(""));
+        for rules in ["v1", "v5", "", "n"] {
+            assert!(candidates[0].accepts(rules), "{rules}");
+        }
+    }
+
+    #[test]
+    fn same_base_by_same_reasons_is_one_candidate() {
+        let candidates = Deinflector::new().unwrap().deinflect("来なかった");
+        let kuru: Vec<_> = candidates.iter().filter(|d| d.term == "来る").collect();
+        assert_eq!(kuru.len(), 1);
+        assert!(kuru[0].accepts("vk") && kuru[0].accepts("v1"));
+        assert_eq!(kuru[0].reasons, ["negative", "past"]);
+    }
+}
@@ -0,0 +1,634 @@
+use std::collections::HashSet;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+use crate::styles;
+use crate::yomitan_import::{compare_revisions, create_indexes, create_schema, INLINE_FTS_OFFSET};
+
+#[derive(Serialize)]
+pub struct DictInfo {
+    pub id: i64,
+    /// The title set with `set_title`, else the one from index.json.
+    pub title: String,
+    /// index.json's title, which duplicate detection goes by.
+    pub original_title: String,
+    pub revision: String,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub format_version: Option<i64>,
+    /// Whether `sequence` groups the senses of one headword across term entries.
+    pub sequenced: bool,
+    /// `"rank-based"` (lower is more common) or `"occurrence-based"` (higher is).
+    pub frequency_mode: String,
+    pub is_bundled: bool,
+    /// The dictionary of terms added with `add_custom_term`.
+    pub is_custom: bool,
+    pub sort_order: i64,
+    /// Added to each term's score when ranking lookups.
+    pub score_offset: i64,
+    pub enabled: bool,
+    pub installed_at: String,
+    /// When a lookup last returned one of its entries, give or take
+    /// `usage::USAGE_THROTTLE_SECS`; `None` if never.
+    pub last_used_at: Option<String>,
+    pub term_count: i64,
+}
+
+/// Installed dictionaries in priority order, bar any still being imported.
+pub fn list(conn: &Connection) -> rusqlite::Result<Vec<DictInfo>> {
+    let mut stmt = conn.prepare(
+        "SELECT d.id, COALESCE(d.display_title, d.title), d.title, d.revision, d.author, d.description, d.format_version, d.sequenced, d.frequency_mode, d.is_bundled, d.is_custom, d.sort_order, d.score_offset, d.enabled, d.installed_at, d.last_used_at,
+                COALESCE(c.n, 0)
+         FROM dictionaries d
+         LEFT JOIN (SELECT dict_id, COUNT(*) AS n FROM terms GROUP BY dict_id) c ON c.dict_id = d.id
+         WHERE d.id NOT IN (SELECT dict_id FROM import_progress)
+         ORDER BY d.sort_order, d.id",
+    )?;
+    let rows = stmt.query_map([], |r| {
+        Ok(DictInfo {
+            id: r.get(0)?,
+            title: r.get(1)?,
+            original_title: r.get(2)?,
+            revision: r.get(3)?,
+            author: r.get(4)?,
+            description: r.get(5)?,
+            format_version: r.get(6)?,
+            sequenced: r.get(7)?,
+            frequency_mode: r.get(8)?,
+            is_bundled: r.get(9)?,
+            is_custom: r.get(10)?,
+            sort_order: r.get(11)?,
+            score_offset: r.get(12)?,
+            enabled: r.get(13)?,
+            installed_at: r.get(14)?,
+            last_used_at: r.get(15)?,
+            term_count: r.get(16)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// (id, revision) of each installed dictionary titled `title`; more than one
+/// when revisions were imported side by side. One still being imported, or
+/// left half imported by a crash, doesn't count.
+pub fn installed(conn: &Connection, title: &str) -> rusqlite::Result<Vec<(i64, String)>> {
+    conn.prepare_cached("SELECT id, revision FROM dictionaries WHERE title = ?1 AND id NOT IN (SELECT dict_id FROM import_progress)")?
+        .query_map(params![title], |r| Ok((r.get(0)?, r.get(1)?)))?
+        .collect()
+}
+
+/// Whether importing (`title`, `revision`) would be skipped as already installed.
+pub fn is_installed(conn: &Connection, title: &str, revision: &str) -> rusqlite::Result<bool> {
+    Ok(installed(conn, title.trim())?.iter().any(|(_, rev)| rev == revision.trim()))
+}
+
+/// The newest installed revision of `title`, if any revision is installed.
+pub fn installed_revision(conn: &Connection, title: &str) -> rusqlite::Result<Option<String>> {
+    Ok(installed(conn, title.trim())?
+        .into_iter()
+        .map(|(_, rev)| rev)
+        .max_by(|a, b| compare_revisions(a, b)))
+}
+
+/// An installed dictionary whose index.json says where to find its latest revision.
+pub struct Updatable {
+    pub id: i64,
+    pub title: String,
+    pub revision: String,
+    pub index_url: String,
+    pub download_url: Option<String>,
+}
+
+/// Installed dictionaries marked `isUpdatable` with an `indexUrl`, in priority order.
+pub fn updatable(conn: &Connection) -> rusqlite::Result<Vec<Updatable>> {
+    conn.prepare(
+        "SELECT id, COALESCE(display_title, title), revision, index_url, download_url FROM dictionaries
+         WHERE is_updatable = 1 AND index_url IS NOT NULL AND id NOT IN (SELECT dict_id FROM import_progress)
+         ORDER BY sort_order, id",
+    )?
+    .query_map([], |r| {
+        Ok(Updatable {
+            id: r.get(0)?,
+            title: r.get(1)?,
+            revision: r.get(2)?,
+            index_url: r.get(3)?,
+            download_url: r.get(4)?,
+        })
+    })?
+    .collect()
+}
+
+/// Everything stored about one dictionary, for its info page.
+#[derive(Serialize)]
+pub struct DictDetail {
+    pub id: i64,
+    pub title: String,
+    pub original_title: String,
+    pub revision: String,
+    pub author: Option<String>,
+    pub url: Option<String>,
+    pub description: Option<String>,
+    pub format_version: Option<i64>,
+    pub sequenced: bool,
+    pub frequency_mode: String,
+    pub is_bundled: bool,
+    pub is_custom: bool,
+    pub sort_order: i64,
+    pub score_offset: i64,
+    pub enabled: bool,
+    /// index.json's `isUpdatable`: `index_url` points at the latest index.json.
+    pub is_updatable: bool,
+    pub index_url: Option<String>,
+    pub download_url: Option<String>,
+    pub installed_at: String,
+    pub last_used_at: Option<String>,
+    pub terms: i64,
+    pub term_meta: i64,
+    pub kanji: i64,
+    pub kanji_meta: i64,
+    pub tags: i64,
+}
+
+pub fn detail(conn: &Connection, id: i64) -> anyhow::Result<DictDetail> {
+    let detail = conn
+        .query_row(
+            "SELECT id, COALESCE(display_title, title), title, revision, author, url, description, format_version, sequenced, frequency_mode, is_bundled, is_custom, sort_order, score_offset, enabled, is_updatable, index_url, download_url, installed_at, last_used_at,
+                    (SELECT COUNT(*) FROM terms      WHERE dict_id = d.id),
+                    (SELECT COUNT(*) FROM term_meta  WHERE dict_id = d.id),
+                    (SELECT COUNT(*) FROM kanji      WHERE dict_id = d.id),
+                    (SELECT COUNT(*) FROM kanji_meta WHERE dict_id = d.id),
+                    (SELECT COUNT(*) FROM tags       WHERE dict_id = d.id)
+             FROM dictionaries d WHERE id = ?1",
+            params![id],
+            |r| {
+                Ok(DictDetail {
+                    id: r.get(0)?,
+                    title: r.get(1)?,
+                    original_title: r.get(2)?,
+                    revision: r.get(3)?,
+                    author: r.get(4)?,
+                    url: r.get(5)?,
+                    description: r.get(6)?,
+                    format_version: r.get(7)?,
+                    sequenced: r.get(8)?,
+                    frequency_mode: r.get(9)?,
+                    is_bundled: r.get(10)?,
+                    is_custom: r.get(11)?,
+                    sort_order: r.get(12)?,
+                    score_offset: r.get(13)?,
+                    enabled: r.get(14)?,
+                    is_updatable: r.get(15)?,
+                    index_url: r.get(16)?,
+                    download_url: r.get(17)?,
+                    installed_at: r.get(18)?,
+                    last_used_at: r.get(19)?,
+                    terms: r.get(20)?,
+                    term_meta: r.get(21)?,
+                    kanji: r.get(22)?,
+                    kanji_meta: r.get(23)?,
+                    tags: r.get(24)?,
+                })
+            },
+        )
+        .optional()?;
+    detail.ok_or_else(|| anyhow::anyhow!("no dictionary with id {}", id))
+}
+
+#[derive(Serialize)]
+pub struct DictTermCount {
+    pub id: i64,
+    pub title: String,
+    pub terms: i64,
+}
+
+#[derive(Serialize)]
+pub struct DbStats {
+    pub dictionaries: i64,
+    pub terms: i64,
+    pub term_meta: i64,
+    pub kanji: i64,
+    pub kanji_meta: i64,
+    pub tags: i64,
+    pub glossaries: i64,
+    /// Imports cut short that are kept for resuming; see `pending_imports`.
+    /// Their rows are left out of the counts above.
+    pub pending_imports: i64,
+    /// Size of the main database file; the WAL is not included.
+    pub size_bytes: i64,
+    /// The journal mode in effect, lowercase as SQLite reports it.
+    pub journal_mode: String,
+    pub per_dictionary: Vec<DictTermCount>,
+}
+
+fn db_size(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.query_row(
+        "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+        [],
+        |r| r.get(0),
+    )
+}
+
+/// Row counts of the main tables, bar those of pending imports, and the database size.
+pub fn stats(conn: &Connection) -> rusqlite::Result<DbStats> {
+    let count = |table: &str| {
+        let column = if table == "dictionaries" { "id" } else { "dict_id" };
+        conn.query_row(
+            &format!("SELECT COUNT(*) FROM {table} WHERE {column} NOT IN (SELECT dict_id FROM import_progress)"),
+            [],
+            |r| r.get::<_, i64>(0),
+        )
+    };
+    let per_dictionary = list(conn)?
+        .into_iter()
+        .map(|d| DictTermCount {
+            id: d.id,
+            title: d.title,
+            terms: d.term_count,
+        })
+        .collect();
+    Ok(DbStats {
+        dictionaries: count("dictionaries")?,
+        terms: count("terms")?,
+        term_meta: count("term_meta")?,
+        kanji: count("kanji")?,
+        kanji_meta: count("kanji_meta")?,
+        tags: count("tags")?,
+        glossaries: conn.query_row("SELECT COUNT(*) FROM glossaries", [], |r| r.get(0))?,
+        pending_imports: conn.query_row("SELECT COUNT(*) FROM import_progress", [], |r| r.get(0))?,
+        size_bytes: db_size(conn)?,
+        journal_mode: conn.query_row("PRAGMA journal_mode", [], |r| r.get(0))?,
+        per_dictionary,
+    })
+}
+
+/// What `check_integrity` found; both lists are empty for a sound database.
+#[derive(Serialize)]
+pub struct IntegrityReport {
+    /// What `PRAGMA integrity_check` reported, bar its lone `ok`.
+    pub problems: Vec<String>,
+    /// Rows whose foreign key names a row that isn't there.
+    pub orphans: Vec<OrphanCount>,
+}
+
+#[derive(Serialize)]
+pub struct OrphanCount {
+    pub table: String,
+    /// The table the missing rows would be in.
+    pub parent: String,
+    pub rows: i64,
+}
+
+/// Run SQLite's integrity and foreign key checks over the whole database.
+pub fn check_integrity(conn: &Connection) -> rusqlite::Result<IntegrityReport> {
+    let problems = conn
+        .prepare("PRAGMA integrity_check")?
+        .query_map([], |r| r.get::<_, String>(0))?
+        .filter(|m| !matches!(m, Ok(m) if m == "ok"))
+        .collect::<rusqlite::Result<_>>()?;
+    let orphans = conn
+        .prepare(r#"SELECT "table", parent, COUNT(*) FROM pragma_foreign_key_check GROUP BY "table", parent ORDER BY "table", parent"#)?
+        .query_map([], |r| {
+            Ok(OrphanCount {
+                table: r.get(0)?,
+                parent: r.get(1)?,
+                rows: r.get(2)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(IntegrityReport { problems, orphans })
+}
+
+/// Delete the rows `check_integrity` counts as orphans, with whatever
+/// cascades from them, then interned rows nothing uses any more. Returns
+/// the number of orphans deleted.
+pub fn remove_orphans(conn: &mut Connection) -> rusqlite::Result<usize> {
+    let tx = conn.transaction()?;
+    let keys = tx
+        .prepare(
+            r#"SELECT m.name, k."table", k."from", COALESCE(k."to", 'rowid')
+               FROM sqlite_schema m, pragma_foreign_key_list(m.name) k
+               WHERE m.type = 'table'"#,
+        )?
+        .query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?, r.get::<_, String>(2)?, r.get::<_, String>(3)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let mut removed = 0;
+    for (table, parent, from, to) in keys {
+        removed += tx.execute(
+            &format!("DELETE FROM {table} WHERE {from} IS NOT NULL AND {from} NOT IN (SELECT {to} FROM {parent})"),
+            [],
+        )?;
+    }
+    collect_garbage(&tx)?;
+    tx.commit()?;
+    Ok(removed)
+}
+
+/// The dictionary's `styles.css`, with every selector already scoped by
+/// `styles::dictionary_scope`, or `None` if it shipped without one.
+pub fn styles(conn: &Connection, id: i64) -> anyhow::Result<Option<String>> {
+    conn.query_row("SELECT styles FROM dictionaries WHERE id = ?1", params![id], |r| r.get(0))
+        .optional()?
+        .ok_or_else(|| anyhow::anyhow!("no dictionary with id {}", id))
+}
+
+/// Show `title` for a dictionary in place of its index.json title, or go
+/// back to that if `title` is blank. Duplicate detection keeps using the
+/// index.json title.
+pub fn set_title(conn: &Connection, id: i64, title: &str) -> anyhow::Result<()> {
+    let title = Some(title.trim()).filter(|t| !t.is_empty());
+    let updated = conn.execute("UPDATE dictionaries SET display_title = ?2 WHERE id = ?1", params![id, title])?;
+    if updated == 0 {
+        anyhow::bail!("no dictionary with id {}", id);
+    }
+    Ok(())
+}
+
+/// The media file at `path` in dictionary `id`, as its glossaries refer to it.
+pub fn media(conn: &Connection, id: i64, path: &str) -> anyhow::Result<Vec<u8>> {
+    conn.query_row("SELECT data FROM media WHERE dict_id = ?1 AND path = ?2 AND data IS NOT NULL", params![id, path], |r| r.get(0))
+        .optional()?
+        .ok_or_else(|| anyhow::anyhow!("no media {:?} in dictionary {}", path, id))
+}
+
+/// Hide or restore a dictionary's entries in lookups without uninstalling it.
+pub fn set_enabled(conn: &Connection, id: i64, enabled: bool) -> anyhow::Result<()> {
+    let updated = conn.execute("UPDATE dictionaries SET enabled = ?2 WHERE id = ?1", params![id, enabled])?;
+    if updated == 0 {
+        anyhow::bail!("no dictionary with id {}", id);
+    }
+    Ok(())
+}
+
+/// Boost (or, if negative, demote) a dictionary's terms against others of the
+/// same priority without re-importing it.
+pub fn set_score_offset(conn: &Connection, id: i64, score_offset: i64) -> anyhow::Result<()> {
+    let updated = conn.execute("UPDATE dictionaries SET score_offset = ?2 WHERE id = ?1", params![id, score_offset])?;
+    if updated == 0 {
+        anyhow::bail!("no dictionary with id {}", id);
+    }
+    Ok(())
+}
+
+/// Set lookup priority to the position of each id in `ordered_ids`, which must
+/// name every installed dictionary exactly once. Pending imports, which `list`
+/// leaves out, keep their place.
+pub fn reorder(conn: &mut Connection, ordered_ids: &[i64]) -> anyhow::Result<()> {
+    let tx = conn.transaction()?;
+    let installed = tx
+        .prepare("SELECT id FROM dictionaries WHERE id NOT IN (SELECT dict_id FROM import_progress)")?
+        .query_map([], |r| r.get(0))?
+        .collect::<rusqlite::Result<HashSet<i64>>>()?;
+    let given: HashSet<i64> = ordered_ids.iter().copied().collect();
+    if given.len() != ordered_ids.len() || given != installed {
+        anyhow::bail!("dictionary order must list each installed dictionary exactly once");
+    }
+
+    let mut update = tx.prepare("UPDATE dictionaries SET sort_order = ?2 WHERE id = ?1")?;
+    for (i, id) in ordered_ids.iter().enumerate() {
+        update.execute(params![id, i as i64])?;
+    }
+    drop(update);
+    tx.commit()?;
+    Ok(())
+}
+
+/// An import cut short by a crash, kept so importing the same file again
+/// resumes it. Until then it is hidden from `list` and lookups.
+#[derive(Serialize)]
+pub struct PendingImport {
+    pub id: i64,
+    pub title: String,
+    pub revision: String,
+    /// Terms committed so far.
+    pub terms: i64,
+}
+
+pub fn pending_imports(conn: &Connection) -> rusqlite::Result<Vec<PendingImport>> {
+    conn.prepare(
+        "SELECT d.id, d.title, d.revision, p.terms FROM import_progress p JOIN dictionaries d ON d.id = p.dict_id ORDER BY d.id",
+    )?
+    .query_map([], |r| {
+        Ok(PendingImport {
+            id: r.get(0)?,
+            title: r.get(1)?,
+            revision: r.get(2)?,
+            terms: r.get(3)?,
+        })
+    })?
+    .collect()
+}
+
+/// Give up on resuming pending import `id`: delete what it committed, as
+/// `uninstall` does. Returns the number of glossaries freed.
+pub fn discard_pending(conn: &mut Connection, id: i64) -> anyhow::Result<usize> {
+    let tx = conn.transaction()?;
+    let deleted = tx.execute("DELETE FROM dictionaries WHERE id = ?1 AND id IN (SELECT dict_id FROM import_progress)", params![id])?;
+    if deleted == 0 {
+        anyhow::bail!("no pending import with id {}", id);
+    }
+    let reclaimed = collect_garbage(&tx)?;
+    tx.commit()?;
+    Ok(reclaimed)
+}
+
+/// Delete a dictionary and everything cascading from it, then drop interned
+/// rows no remaining term references. Returns the number of glossaries freed.
+pub fn uninstall(conn: &mut Connection, id: i64) -> anyhow::Result<usize> {
+    let tx = conn.transaction()?;
+    let deleted = tx.execute("DELETE FROM dictionaries WHERE id = ?1", params![id])?;
+    if deleted == 0 {
+        anyhow::bail!("no dictionary with id {}", id);
+    }
+    let reclaimed = collect_garbage(&tx)?;
+    tx.commit()?;
+
+    Ok(reclaimed)
+}
+
+/// Tables whose rows `merge` moves to the kept dictionary. A tag or media
+/// file the kept one has too stays behind and goes with the dropped one.
+/// Styles are appended to the kept one's rather than moved.
+const MERGED_TABLES: &[&str] = &["terms", "term_meta", "tags", "kanji", "kanji_meta", "media"];
+
+/// Move every row of dictionary `drop_id` to `keep_id`, then delete `drop_id`.
+/// Meant for the same dictionary installed twice, so both must read
+/// frequencies the same way. Returns the number of rows moved.
+pub fn merge(conn: &mut Connection, keep_id: i64, drop_id: i64) -> anyhow::Result<usize> {
+    if keep_id == drop_id {
+        anyhow::bail!("cannot merge dictionary {} into itself", keep_id);
+    }
+    let tx = conn.transaction()?;
+    let mut modes = vec![];
+    for id in [keep_id, drop_id] {
+        let row = tx
+            .query_row(
+                "SELECT is_custom, frequency_mode FROM dictionaries WHERE id = ?1 AND id NOT IN (SELECT dict_id FROM import_progress)",
+                params![id],
+                |r| Ok((r.get::<_, bool>(0)?, r.get::<_, String>(1)?)),
+            )
+            .optional()?;
+        match row {
+            None => anyhow::bail!("no dictionary with id {}", id),
+            Some((true, _)) => anyhow::bail!("the user dictionary cannot be merged"),
+            Some((false, mode)) => modes.push(mode),
+        }
+    }
+    if modes[0] != modes[1] {
+        anyhow::bail!("cannot merge a {} dictionary into a {} one", modes[1], modes[0]);
+    }
+    let mut moved = 0;
+    for table in MERGED_TABLES {
+        moved += tx.execute(&format!("UPDATE OR IGNORE {table} SET dict_id = ?1 WHERE dict_id = ?2"), params![keep_id, drop_id])?;
+    }
+    // The dropped one's structured content still needs its styles, scoped to where its entries now are
+    let dropped_styles: Option<String> = tx.query_row("SELECT styles FROM dictionaries WHERE id = ?1", params![drop_id], |r| r.get(0))?;
+    if let Some(css) = dropped_styles.filter(|css| !css.trim().is_empty()) {
+        let css = css.replace(&styles::dictionary_scope(drop_id), &styles::dictionary_scope(keep_id));
+        tx.execute(
+            "UPDATE dictionaries SET styles = CASE WHEN COALESCE(styles, '') = '' THEN ?2 ELSE styles || char(10) || ?2 END WHERE id = ?1",
+            params![keep_id, css],
+        )?;
+    }
+    tx.execute("DELETE FROM dictionaries WHERE id = ?1", params![drop_id])?;
+    tx.commit()?;
+    Ok(moved)
+}
+
+/// Drop interned rows no term references any more. Returns the number of glossaries freed.
+pub fn collect_garbage(conn: &Connection) -> rusqlite::Result<usize> {
+    let reclaimed = conn.execute("DELETE FROM glossaries WHERE id NOT IN (SELECT glossary_id FROM terms WHERE glossary_id IS NOT NULL)", [])?;
+    conn.execute_batch(&format!(
+        "DELETE FROM glossary_fts   WHERE rowid NOT IN (SELECT id FROM glossaries)
+                                      AND NOT EXISTS (SELECT 1 FROM terms WHERE id = glossary_fts.rowid + {INLINE_FTS_OFFSET});
+        DELETE FROM def_tag_sets   WHERE id NOT IN (SELECT def_tags_id  FROM terms WHERE def_tags_id  IS NOT NULL);
+        DELETE FROM rule_sets      WHERE id NOT IN (SELECT rules_id     FROM terms WHERE rules_id     IS NOT NULL);
+        DELETE FROM term_tag_sets  WHERE id NOT IN (SELECT term_tags_id FROM terms WHERE term_tags_id IS NOT NULL);"
+    ))?;
+    Ok(reclaimed)
+}
+
+/// Rewrite the database file to reclaim space left by uninstalled or replaced
+/// dictionaries. Returns the number of bytes freed.
+pub fn compact(conn: &Connection) -> rusqlite::Result<i64> {
+    let before = db_size(conn)?;
+    conn.execute_batch("VACUUM;")?;
+    Ok(before - db_size(conn)?)
+}
+
+/// What `reset` must be passed to go ahead, so no stray call wipes the database.
+pub const RESET_CONFIRMATION: &str = "delete all dictionaries";
+
+/// Every table `create_schema` makes bar `schema_meta` and `bundled_imports`,
+/// children before parents. Bundled zips stay recorded, so a reset database
+/// isn't refilled with them.
+const CONTENT_TABLES: &[&str] = &[
+    "glossary_fts",
+    "term_tag_link",
+    "terms",
+    "term_meta",
+    "tags",
+    "kanji",
+    "kanji_meta",
+    "media",
+    "glossaries",
+    "def_tag_sets",
+    "term_tag_sets",
+    "rule_sets",
+    "import_progress",
+    "dictionaries",
+];
+
+/// Drop every dictionary and its data by recreating the content tables
+/// empty, then VACUUM. `schema_meta` is kept, so no migrations re-run;
+/// `created_at` is restamped unless `keep_created_at`.
+pub fn reset(conn: &Connection, confirm: &str, keep_created_at: bool) -> anyhow::Result<()> {
+    if confirm != RESET_CONFIRMATION {
+        anyhow::bail!("reset not confirmed: pass {:?} as the confirmation", RESET_CONFIRMATION);
+    }
+    let tx = conn.unchecked_transaction()?;
+    for table in CONTENT_TABLES {
+        tx.execute_batch(&format!("DROP TABLE IF EXISTS {table}"))?;
+    }
+    create_schema(&tx)?;
+    create_indexes(&tx)?;
+    if !keep_created_at {
+        tx.execute("UPDATE schema_meta SET value = datetime('now') WHERE key = 'created_at'", [])?;
+    }
+    tx.commit()?;
+    conn.execute_batch("VACUUM;")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Installed dictionary 1 and dictionary 2 as a crash leaves a resumable
+    /// import: disabled, with a committed term and its `import_progress`.
+    fn db_with_pending_import() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("PRAGMA foreign_keys = ON;").unwrap();
+        create_schema(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO dictionaries (id, title, revision) VALUES (1, 'Installed', '1');
+             INSERT INTO dictionaries (id, title, revision, enabled) VALUES (2, 'Pending', '1', 0);
+             INSERT INTO import_progress (dict_id, banks, terms) VALUES (2, 1, 1);
+             INSERT INTO glossaries (id, hash, content) VALUES (1, 'a', '[\"one\"]'), (2, 'b', '[\"two\"]');
+             INSERT INTO terms (dict_id, term, reading, glossary_id) VALUES (1, '一', 'いち', 1), (2, '二', 'に', 2);",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn pending_imports_stay_out_of_reorder_and_stats() {
+        let mut conn = db_with_pending_import();
+        assert_eq!(list(&conn).unwrap().iter().map(|d| d.id).collect::<Vec<_>>(), [1]);
+        reorder(&mut conn, &[1]).unwrap();
+        assert!(reorder(&mut conn, &[1, 2]).is_err());
+
+        let stats = stats(&conn).unwrap();
+        assert_eq!((stats.dictionaries, stats.terms, stats.pending_imports), (1, 1, 1));
+        let pending = pending_imports(&conn).unwrap();
+        assert_eq!(pending.iter().map(|p| (p.id, p.title.as_str(), p.terms)).collect::<Vec<_>>(), [(2, "Pending", 1)]);
+    }
+
+    #[test]
+    fn merge_carries_the_dropped_dictionarys_styles() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("PRAGMA foreign_keys = ON;").unwrap();
+        create_schema(&conn).unwrap();
+        let scoped = |id, css: &str| styles::scope_css(css, &styles::dictionary_scope(id));
+        for (id, css) in [(1, Some(".gloss { color: red; }")), (2, Some(".example { font-size: 90%; }")), (3, None), (4, Some("span { font-weight: bold; }"))] {
+            conn.execute(
+                "INSERT INTO dictionaries (id, title, revision, styles) VALUES (?1, ?2, '1', ?3)",
+                params![id, format!("D{id}"), css.map(|css| scoped(id, css))],
+            )
+            .unwrap();
+        }
+        conn.execute("INSERT INTO terms (dict_id, term, reading) VALUES (2, '例', 'れい')", []).unwrap();
+
+        merge(&mut conn, 1, 2).unwrap();
+        let kept = styles(&conn, 1).unwrap().unwrap();
+        assert_eq!(kept, format!("{}\n{}", scoped(1, ".gloss { color: red; }"), scoped(1, ".example { font-size: 90%; }")));
+        assert!(!kept.contains(&styles::dictionary_scope(2)));
+
+        // A kept dictionary without styles takes the dropped one's as they are
+        merge(&mut conn, 3, 4).unwrap();
+        assert_eq!(styles(&conn, 3).unwrap(), Some(scoped(3, "span { font-weight: bold; }")));
+    }
+
+    #[test]
+    fn discarding_a_pending_import_frees_its_rows() {
+        let mut conn = db_with_pending_import();
+        assert!(discard_pending(&mut conn, 1).is_err(), "an installed dictionary is no pending import");
+        assert_eq!(discard_pending(&mut conn, 2).unwrap(), 1);
+        assert!(pending_imports(&conn).unwrap().is_empty());
+        let count = |sql: &str| conn.query_row(sql, [], |r| r.get::<_, i64>(0)).unwrap();
+        assert_eq!(count("SELECT COUNT(*) FROM dictionaries"), 1);
+        assert_eq!(count("SELECT COUNT(*) FROM terms"), 1);
+        assert_eq!(count("SELECT COUNT(*) FROM glossaries"), 1);
+        assert_eq!(count("SELECT COUNT(*) FROM import_progress"), 0);
+    }
+}
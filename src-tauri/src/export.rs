@@ -0,0 +1,307 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::Context;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde_json::{json, Value};
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use crate::term_meta;
+
+/// Entries per bank file `export_dictionary` writes by default, matching what
+/// Yomitan's own exports use.
+pub const DEFAULT_ENTRIES_PER_BANK: usize = 10_000;
+
+fn json_column(r: &Row, i: usize) -> rusqlite::Result<Value> {
+    let s: String = r.get(i)?;
+    Ok(serde_json::from_str(&s).unwrap_or(Value::String(s)))
+}
+
+/// Write the rows of `sql` (bound to `dict_id`) as `{prefix}_{n}.json` banks
+/// of at most `per_bank` entries each, numbered from 1.
+fn write_banks(
+    zip: &mut ZipWriter<File>,
+    conn: &Connection,
+    prefix: &str,
+    sql: &str,
+    dict_id: i64,
+    per_bank: usize,
+    entry: impl Fn(&Row) -> rusqlite::Result<Value>,
+) -> anyhow::Result<()> {
+    let mut stmt = conn.prepare(sql)?;
+    let mut rows = stmt.query(params![dict_id])?;
+    let mut bank = Vec::with_capacity(per_bank);
+    let mut bank_i = 1;
+    loop {
+        let row = rows.next()?;
+        if let Some(r) = row {
+            bank.push(entry(r)?);
+        }
+        if bank.len() == per_bank || (row.is_none() && !bank.is_empty()) {
+            zip.start_file(format!("{}_{}.json", prefix, bank_i), FileOptions::default())?;
+            zip.write_all(&serde_json::to_vec(&bank)?)?;
+            bank.clear();
+            bank_i += 1;
+        }
+        if row.is_none() {
+            return Ok(());
+        }
+    }
+}
+
+/// Write dictionary `dict_id` to `out_path` as a format 3 Yomitan zip, with
+/// at most `entries_per_bank` entries in each bank file.
+pub fn export_dictionary(conn: &Connection, dict_id: i64, out_path: &Path, entries_per_bank: usize) -> anyhow::Result<()> {
+    if entries_per_bank == 0 {
+        anyhow::bail!("entries_per_bank must be at least 1");
+    }
+    let index = conn
+        .query_row(
+            "SELECT title, revision, author, url, description, sequenced, frequency_mode, is_updatable, index_url, download_url FROM dictionaries WHERE id = ?1",
+            params![dict_id],
+            |r| {
+                let mut index = json!({
+                    "title": r.get::<_, String>(0)?,
+                    "revision": r.get::<_, String>(1)?,
+                    "format": 3,
+                    "sequenced": r.get::<_, bool>(5)?,
+                    "frequencyMode": r.get::<_, String>(6)?,
+                });
+                if r.get::<_, bool>(7)? {
+                    index["isUpdatable"] = Value::Bool(true);
+                }
+                for (i, key) in [(2, "author"), (3, "url"), (4, "description"), (8, "indexUrl"), (9, "downloadUrl")] {
+                    if let Some(v) = r.get::<_, Option<String>>(i)? {
+                        index[key] = Value::String(v);
+                    }
+                }
+                Ok(index)
+            },
+        )
+        .optional()?
+        .with_context(|| format!("no dictionary with id {}", dict_id))?;
+
+    let f = File::create(out_path).with_context(|| format!("creating {}", out_path.display()))?;
+    let mut zip = ZipWriter::new(f);
+    zip.start_file("index.json", FileOptions::default())?;
+    zip.write_all(&serde_json::to_vec(&index)?)?;
+
+    write_banks(
+        &mut zip,
+        conn,
+        "term_bank",
+        "SELECT t.term, t.reading, COALESCE(dt.tags, ''), COALESCE(r.rules, ''), t.score, COALESCE(g.content, t.glossary), t.sequence, COALESCE(tt.tags, '')
+         FROM terms t
+         LEFT JOIN glossaries g ON g.id = t.glossary_id
+         LEFT JOIN def_tag_sets  dt ON dt.id = t.def_tags_id
+         LEFT JOIN rule_sets      r ON r.id  = t.rules_id
+         LEFT JOIN term_tag_sets tt ON tt.id = t.term_tags_id
+         WHERE t.dict_id = ?1 ORDER BY t.id",
+        dict_id,
+        entries_per_bank,
+        |r| {
+            Ok(json!([
+                r.get::<_, String>(0)?,
+                r.get::<_, String>(1)?,
+                r.get::<_, String>(2)?,
+                r.get::<_, String>(3)?,
+                r.get::<_, i64>(4)?,
+                json_column(r, 5)?,
+                // Yomitan requires a sequence number; 0 stands for none
+                r.get::<_, Option<i64>>(6)?.unwrap_or(0),
+                r.get::<_, String>(7)?,
+            ]))
+        },
+    )?;
+    write_banks(
+        &mut zip,
+        conn,
+        "term_meta_bank",
+        "SELECT term, mode, data FROM term_meta WHERE dict_id = ?1 ORDER BY id",
+        dict_id,
+        entries_per_bank,
+        |r| Ok(json!([r.get::<_, String>(0)?, r.get::<_, String>(1)?, json_column(r, 2)?])),
+    )?;
+    write_banks(
+        &mut zip,
+        conn,
+        "tag_bank",
+        "SELECT name, COALESCE(category, ''), sort_order, COALESCE(notes, ''), score FROM tags WHERE dict_id = ?1 ORDER BY id",
+        dict_id,
+        entries_per_bank,
+        |r| {
+            Ok(json!([
+                r.get::<_, String>(0)?,
+                r.get::<_, String>(1)?,
+                r.get::<_, i64>(2)?,
+                r.get::<_, String>(3)?,
+                r.get::<_, i64>(4)?,
+            ]))
+        },
+    )?;
+    write_banks(
+        &mut zip,
+        conn,
+        "kanji_bank",
+        "SELECT kanji, COALESCE(onyomi, ''), COALESCE(kunyomi, ''), COALESCE(tags, ''), meanings, stats FROM kanji WHERE dict_id = ?1 ORDER BY id",
+        dict_id,
+        entries_per_bank,
+        |r| {
+            Ok(json!([
+                r.get::<_, String>(0)?,
+                r.get::<_, String>(1)?,
+                r.get::<_, String>(2)?,
+                r.get::<_, String>(3)?,
+                json_column(r, 4)?,
+                json_column(r, 5)?,
+            ]))
+        },
+    )?;
+    write_banks(
+        &mut zip,
+        conn,
+        "kanji_meta_bank",
+        "SELECT character, mode, data FROM kanji_meta WHERE dict_id = ?1 ORDER BY id",
+        dict_id,
+        entries_per_bank,
+        |r| Ok(json!([r.get::<_, String>(0)?, r.get::<_, String>(1)?, json_column(r, 2)?])),
+    )?;
+
+    let mut media = conn.prepare("SELECT path, data FROM media WHERE dict_id = ?1 AND data IS NOT NULL ORDER BY path")?;
+    let mut rows = media.query(params![dict_id])?;
+    while let Some(r) = rows.next()? {
+        zip.start_file(r.get::<_, String>(0)?, FileOptions::default())?;
+        zip.write_all(r.get_ref(1)?.as_blob()?)?;
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+/// Split a space-separated tag list.
+fn tag_list(r: &Row, i: usize) -> rusqlite::Result<Vec<String>> {
+    Ok(r.get::<_, String>(i)?.split_whitespace().map(str::to_string).collect())
+}
+
+/// Write every term of dictionary `dict_id`, or of all installed dictionaries,
+/// to `out_path` as JSON Lines: one object per term with its dictionary,
+/// tags, glossary and the frequencies enabled dictionaries give it. Rows are
+/// written as they are read, so memory stays flat however many there are.
+/// Returns the number of lines written.
+pub fn export_jsonl(conn: &Connection, dict_id: Option<i64>, out_path: &Path) -> anyhow::Result<usize> {
+    if let Some(id) = dict_id {
+        conn.query_row("SELECT 1 FROM dictionaries WHERE id = ?1", params![id], |_| Ok(()))
+            .optional()?
+            .with_context(|| format!("no dictionary with id {}", id))?;
+    }
+    let mut stmt = conn.prepare(
+        "SELECT t.term, t.reading, t.dict_id, COALESCE(d.display_title, d.title), COALESCE(dt.tags, ''), COALESCE(tt.tags, ''),
+                COALESCE(r.rules, ''), t.score, t.sequence, COALESCE(g.content, t.glossary)
+         FROM terms t
+         JOIN dictionaries d ON d.id = t.dict_id
+         LEFT JOIN glossaries g ON g.id = t.glossary_id
+         LEFT JOIN def_tag_sets  dt ON dt.id = t.def_tags_id
+         LEFT JOIN rule_sets      r ON r.id  = t.rules_id
+         LEFT JOIN term_tag_sets tt ON tt.id = t.term_tags_id
+         WHERE (?1 IS NULL OR t.dict_id = ?1) AND t.dict_id NOT IN (SELECT dict_id FROM import_progress)
+         ORDER BY d.sort_order, t.id",
+    )?;
+    let f = File::create(out_path).with_context(|| format!("creating {}", out_path.display()))?;
+    let mut out = BufWriter::new(f);
+    let mut rows = stmt.query(params![dict_id])?;
+    let mut lines = 0;
+    while let Some(r) = rows.next()? {
+        let term: String = r.get(0)?;
+        let reading: String = r.get(1)?;
+        let frequencies = term_meta::frequencies(conn, &term, &reading)?;
+        let line = json!({
+            "term": term,
+            "reading": reading,
+            "dict_id": r.get::<_, i64>(2)?,
+            "dictionary": r.get::<_, String>(3)?,
+            "def_tags": tag_list(r, 4)?,
+            "term_tags": tag_list(r, 5)?,
+            "rules": tag_list(r, 6)?,
+            "score": r.get::<_, i64>(7)?,
+            "sequence": r.get::<_, Option<i64>>(8)?,
+            "glossary": json_column(r, 9)?,
+            "frequencies": frequencies,
+        });
+        serde_json::to_writer(&mut out, &line)?;
+        out.write_all(b"\n")?;
+        lines += 1;
+    }
+    out.flush()?;
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yomitan_import::{import_one, open_db, CancellationToken, DbOptions, OnConflict, DEFAULT_BATCH_ROWS};
+
+    const TABLES: [&str; 5] = ["terms", "term_meta", "tags", "kanji", "kanji_meta"];
+
+    fn import(conn: &Connection, path: &Path) {
+        let summary = import_one(conn, path, false, OnConflict::ReplaceOlder, DEFAULT_BATCH_ROWS, true, &CancellationToken::default(), |_| {}).unwrap();
+        assert!(summary.terms > 0, "{} imported nothing", path.display());
+    }
+
+    fn counts(conn: &Connection) -> Vec<i64> {
+        TABLES
+            .iter()
+            .map(|table| conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |r| r.get(0)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn export_reimports_whole_at_any_bank_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let dict = dir.path().join("dict");
+        std::fs::create_dir(&dict).unwrap();
+        let files = [
+            ("index.json", json!({ "title": "Round trip", "revision": "2", "format": 3, "sequenced": true })),
+            (
+                "term_bank_1.json",
+                json!([
+                    ["読む", "よむ", "v5", "v5", 10, ["to read"], 1, "P"],
+                    ["読み", "よみ", "n", "", 0, ["reading"], 2, ""],
+                    ["本", "ほん", "n", "", 5, ["book", "main"], 3, "P"],
+                ]),
+            ),
+            ("term_bank_2.json", json!([["本", "もと", "n", "", -1, [{ "type": "text", "text": "origin" }], 4, ""]])),
+            (
+                "term_meta_bank_1.json",
+                json!([
+                    ["読む", "freq", 120],
+                    ["本", "freq", { "reading": "ほん", "frequency": { "value": 30, "displayValue": "30" } }],
+                    ["本", "pitch", { "reading": "ほん", "pitches": [{ "position": 1 }] }],
+                ]),
+            ),
+            ("tag_bank_1.json", json!([["n", "partOfSpeech", 0, "noun", 0], ["P", "popular", -1, "common word", 10], ["v5", "partOfSpeech", 0, "godan verb", 0]])),
+            ("kanji_bank_1.json", json!([["本", "ホン", "もと", "", ["book"], { "strokes": "5" }], ["読", "ドク", "よ.む", "", ["read"], {}]])),
+            ("kanji_meta_bank_1.json", json!([["本", "freq", 10]])),
+        ];
+        for (name, content) in files {
+            std::fs::write(dict.join(name), content.to_string()).unwrap();
+        }
+        let source = open_db(&dir.path().join("source.db"), &DbOptions::default()).unwrap();
+        import(&source, &dict);
+        let expected = counts(&source);
+        assert_eq!(expected, vec![4, 3, 3, 2, 1]);
+        let dict_id: i64 = source.query_row("SELECT id FROM dictionaries", [], |r| r.get(0)).unwrap();
+
+        for per_bank in [1, 2, DEFAULT_ENTRIES_PER_BANK] {
+            let zip_path = dir.path().join(format!("export-{per_bank}.zip"));
+            export_dictionary(&source, dict_id, &zip_path, per_bank).unwrap();
+            let target = open_db(&dir.path().join(format!("target-{per_bank}.db")), &DbOptions::default()).unwrap();
+            import(&target, &zip_path);
+            assert_eq!(counts(&target), expected, "{per_bank} entries per bank");
+            let (title, revision): (String, String) = target.query_row("SELECT title, revision FROM dictionaries", [], |r| Ok((r.get(0)?, r.get(1)?))).unwrap();
+            assert_eq!((title.as_str(), revision.as_str()), ("Round trip", "2"));
+        }
+        assert!(export_dictionary(&source, dict_id, &dir.path().join("none.zip"), 0).is_err());
+    }
+}
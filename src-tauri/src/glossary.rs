@@ -0,0 +1,125 @@
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// The glossary field of a term bank entry: a single definition or a list of them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Glossary {
+    Many(Vec<Definition>),
+    One(Definition),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Definition {
+    Text(String),
+    Tagged(TaggedDefinition),
+    /// Anything else Yomitan allows, e.g. `[uninflected, [reasons]]`, kept verbatim.
+    Other(Value),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum TaggedDefinition {
+    Text { text: String },
+    Image(Map<String, Value>),
+    StructuredContent { content: Value },
+}
+
+impl Definition {
+    fn into_value(self) -> Value {
+        match self {
+            Definition::Text(text) | Definition::Tagged(TaggedDefinition::Text { text }) => Value::String(text),
+            Definition::Tagged(TaggedDefinition::Image(mut attrs)) => {
+                attrs.insert("type".into(), "image".into());
+                Value::Object(attrs)
+            }
+            Definition::Tagged(TaggedDefinition::StructuredContent { content }) => {
+                let mut o = Map::new();
+                o.insert("type".into(), "structured-content".into());
+                o.insert("content".into(), content);
+                Value::Object(o)
+            }
+            Definition::Other(v) => v,
+        }
+    }
+}
+
+impl Glossary {
+    pub fn parse(v: Value) -> Self {
+        if v.is_null() {
+            return Glossary::Many(vec![]);
+        }
+        // `Definition::Other` accepts any value, so this cannot fail
+        serde_json::from_value(v).unwrap_or(Glossary::Many(vec![]))
+    }
+
+    /// Canonical storage form: always a list, with plain-text definitions as
+    /// bare strings and object keys sorted, so the same content hashes the
+    /// same whatever its input shape or key order.
+    pub fn into_canonical(self) -> Value {
+        let defs = match self {
+            Glossary::Many(defs) => defs,
+            Glossary::One(def) => vec![def],
+        };
+        let mut v = Value::Array(defs.into_iter().map(Definition::into_value).collect());
+        sort_keys(&mut v);
+        v
+    }
+}
+
+/// Sort the keys of every object in `v`. serde_json's `Map` is already
+/// sorted unless its `preserve_order` feature is on, which any crate in the
+/// build can turn on, so the rebuild is usually skipped.
+fn sort_keys(v: &mut Value) {
+    match v {
+        Value::Array(items) => items.iter_mut().for_each(sort_keys),
+        Value::Object(o) => {
+            if !o.keys().is_sorted() {
+                let mut entries: Vec<(String, Value)> = std::mem::take(o).into_iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(&b.0));
+                o.extend(entries);
+            }
+            o.values_mut().for_each(sort_keys);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yomitan_import::{create_schema, import_one, CancellationToken, OnConflict, DEFAULT_BATCH_ROWS};
+    use rusqlite::Connection;
+
+    // The same structured-content glossary, keys written in two orders
+    const SORTED: &str = r#"[{"content":{"content":"to read","data":{"class":"gloss","lang":"en"},"tag":"span"},"type":"structured-content"}]"#;
+    const SHUFFLED: &str = r#"[{"type":"structured-content","content":{"tag":"span","data":{"lang":"en","class":"gloss"},"content":"to read"}}]"#;
+
+    #[test]
+    fn key_order_does_not_change_canonical_form() {
+        let canonical = |raw: &str| serde_json::to_string(&Glossary::parse(serde_json::from_str(raw).unwrap()).into_canonical()).unwrap();
+        assert_eq!(canonical(SHUFFLED), canonical(SORTED));
+        assert_eq!(canonical(SORTED), SORTED);
+    }
+
+    #[test]
+    fn key_order_variants_intern_as_one_row() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_schema(&conn).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        for (name, glossary) in [("sorted", SORTED), ("shuffled", SHUFFLED)] {
+            let dict = dir.path().join(name);
+            std::fs::create_dir(&dict).unwrap();
+            let index = serde_json::json!({ "title": name, "revision": "1", "format": 3 });
+            std::fs::write(dict.join("index.json"), index.to_string()).unwrap();
+            std::fs::write(dict.join("term_bank_1.json"), format!(r#"[["読む", "よむ", "", "", 0, {glossary}, 1, ""]]"#)).unwrap();
+            let summary = import_one(&conn, &dict, false, OnConflict::ReplaceOlder, DEFAULT_BATCH_ROWS, true, &CancellationToken::default(), |_| {}).unwrap();
+            assert_eq!(summary.terms, 1, "{name}");
+        }
+        let count = |sql: &str| conn.query_row(sql, [], |r| r.get::<_, i64>(0)).unwrap();
+        assert_eq!(count("SELECT COUNT(*) FROM terms"), 2);
+        assert_eq!(count("SELECT COUNT(*) FROM glossaries"), 1);
+        assert_eq!(count("SELECT COUNT(DISTINCT glossary_id) FROM terms"), 1);
+    }
+}
@@ -1,51 +1,229 @@
-use lindera::dictionary::{DictionaryKind, load_dictionary_from_kind};
-use lindera::mode::Mode;
-use lindera::segmenter::Segmenter;
-use lindera::tokenizer::Tokenizer;
+use rusqlite::{params, Connection};
+
+use crate::kana::{fold_latin, fold_latin_char, normalize};
+use crate::lookup::{self, TermEntry};
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SegmentKind {
+    /// A headword or reading of an enabled dictionary.
+    Word,
+    /// Whitespace or punctuation.
+    Punct,
+    /// Anything else no dictionary matches.
+    Unknown,
+}
 
 #[derive(serde::Serialize)]
 pub struct SegmentResult {
     t: String,
     r: Vec<String>,
+    /// Whether `t` is a dictionary hit, i.e. `kind` is `Word`.
+    matched: bool,
+    kind: SegmentKind,
+    /// Entries for `t`, only when definitions were asked for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    defs: Option<Vec<TermEntry>>,
 }
 
-pub struct LinderaAnalyzer {
-    tokenizer: lindera::tokenizer::Tokenizer,
+#[derive(Default)]
+struct TrieNode {
+    /// Sorted by char for binary search.
+    children: Vec<(char, u32)>,
+    terminal: bool,
 }
 
-impl LinderaAnalyzer {
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let dictionary = load_dictionary_from_kind(DictionaryKind::IPADIC)?;
-        let segmenter = Segmenter::new(
-            Mode::Normal,
-            dictionary,
-            None, // Assuming no user dictionary is provided
-        );
+/// Prefix trie of every headword and reading in enabled dictionaries, folded
+/// with `fold_latin`, used for longest-match segmentation without touching SQLite.
+pub struct TermIndex {
+    nodes: Vec<TrieNode>,
+    /// Chars in the longest headword or reading, counted once per load, past
+    /// which no match is looked for.
+    max_len: usize,
+}
 
-        // Create a tokenizer.
-        let tokenizer = Tokenizer::new(segmenter);
-        Ok(LinderaAnalyzer { tokenizer })
+impl TermIndex {
+    pub fn load(conn: &Connection) -> rusqlite::Result<Self> {
+        let mut stmt = conn.prepare(
+            "SELECT t.term FROM terms t JOIN dictionaries d ON d.id = t.dict_id AND d.enabled = 1
+             UNION
+             SELECT t.reading FROM terms t JOIN dictionaries d ON d.id = t.dict_id AND d.enabled = 1 WHERE t.reading != ''",
+        )?;
+        // SQLite's `length` counts chars of text, as the walk does
+        let max_len: i64 = conn.query_row(
+            "SELECT COALESCE(MAX(MAX(length(t.term), length(t.reading))), 0)
+             FROM terms t JOIN dictionaries d ON d.id = t.dict_id AND d.enabled = 1",
+            [],
+            |r| r.get(0),
+        )?;
+        let mut index = TermIndex {
+            nodes: vec![TrieNode::default()],
+            max_len: max_len as usize,
+        };
+        for t in stmt.query_map([], |r| r.get::<_, String>(0))? {
+            index.insert(&t?);
+        }
+        Ok(index)
     }
 
-    pub fn analyze(&self, text: &str) -> Result<Vec<SegmentResult>, Box<dyn std::error::Error>> {
-        let tokens = self.tokenizer.tokenize(text)?;
-        let mut result_tokens = Vec::new();
-
-        for token in tokens {
-            let text = token.text.to_string();
-            let details = token
-                .clone()
-                .details()
-                .iter()
-                .map(|f| f.to_string())
-                .collect::<Vec<String>>();
-
-            result_tokens.push(SegmentResult {
-                t: text,
-                r: details,
-            });
+    fn insert(&mut self, term: &str) {
+        let mut node = 0;
+        for c in term.chars().map(fold_latin_char) {
+            node = match self.nodes[node].children.binary_search_by_key(&c, |&(k, _)| k) {
+                Ok(i) => self.nodes[node].children[i].1 as usize,
+                Err(i) => {
+                    let child = self.nodes.len();
+                    self.nodes.push(TrieNode::default());
+                    self.nodes[node].children.insert(i, (c, child as u32));
+                    child
+                }
+            };
         }
+        self.nodes[node].terminal = true;
+    }
+
+    /// Length in chars of the longest known term starting at `chars[start]`;
+    /// `chars` must already be folded.
+    fn longest_match(&self, chars: &[char], start: usize) -> Option<usize> {
+        let mut node = 0;
+        let mut longest = None;
+        let end = chars.len().min(start + self.max_len);
+        for (len, &c) in chars[start..end].iter().enumerate() {
+            match self.nodes[node].children.binary_search_by_key(&c, |&(k, _)| k) {
+                Ok(i) => node = self.nodes[node].children[i].1 as usize,
+                Err(_) => break,
+            }
+            if self.nodes[node].terminal {
+                longest = Some(len + 1);
+            }
+        }
+        longest
+    }
+}
+
+/// Readings of every term whose headword or reading equals `surface`.
+fn readings_of(conn: &Connection, surface: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT DISTINCT CASE WHEN t.reading = '' THEN t.term ELSE t.reading END
+         FROM terms t JOIN dictionaries d ON d.id = t.dict_id AND d.enabled = 1
+         WHERE t.term = ?1 OR t.term_norm = ?1 OR t.reading = ?1",
+    )?;
+    let rows = stmt.query_map(params![fold_latin(surface)], |r| r.get(0))?;
+    rows.collect()
+}
+
+/// Whitespace, ASCII punctuation, general punctuation, and the CJK and
+/// fullwidth punctuation blocks, except marks that are part of words (々 〆 〇).
+fn is_punct(c: char) -> bool {
+    c.is_whitespace()
+        || c.is_ascii_punctuation()
+        || matches!(c,
+            '\u{2000}'..='\u{206F}'
+            | '\u{3000}'..='\u{3004}'
+            | '\u{3008}'..='\u{3020}'
+            | '\u{3030}'
+            | '\u{30FB}'
+            | '\u{FF01}'..='\u{FF0F}'
+            | '\u{FF1A}'..='\u{FF20}'
+            | '\u{FF3B}'..='\u{FF40}'
+            | '\u{FF5B}'..='\u{FF65}'
+        )
+}
+
+/// Characters `segment` takes unless told otherwise.
+pub const DEFAULT_MAX_SEGMENT_CHARS: usize = 10_000;
+
+/// Greedy left-to-right longest-match segmentation against the dictionary.
+/// Characters with no match are emitted one per segment with empty readings.
+/// With `with_definitions`, matched segments also carry their dictionary entries.
+///
+/// Matching walks the trie at most the longest term's length from each
+/// position, so n chars take O(n·L) steps for that length L, plus a query
+/// per matched word (two with definitions). Text longer than `max_chars` is
+/// refused rather than holding the reader that long.
+pub fn segment(
+    conn: &Connection,
+    index: &TermIndex,
+    text: &str,
+    with_definitions: bool,
+    max_chars: usize,
+) -> anyhow::Result<Vec<SegmentResult>> {
+    let n = text.chars().count();
+    if n > max_chars {
+        anyhow::bail!("text is {} characters long; segment takes at most {}", n, max_chars);
+    }
+    let chars: Vec<char> = normalize(text).chars().collect();
+    // Folding maps char to char, so a match in `folded` spans the same chars of `chars`
+    let folded: Vec<char> = chars.iter().copied().map(fold_latin_char).collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match index.longest_match(&folded, i) {
+            Some(len) => {
+                let t: String = chars[i..i + len].iter().collect();
+                let r = readings_of(conn, &t)?;
+                let defs = if with_definitions { Some(lookup::lookup_surface(conn, &t)?) } else { None };
+                out.push(SegmentResult {
+                    t,
+                    r,
+                    matched: true,
+                    kind: SegmentKind::Word,
+                    defs,
+                });
+                i += len;
+            }
+            None => {
+                out.push(SegmentResult {
+                    t: chars[i].to_string(),
+                    r: vec![],
+                    matched: false,
+                    kind: if is_punct(chars[i]) { SegmentKind::Punct } else { SegmentKind::Unknown },
+                    defs: with_definitions.then(Vec::new),
+                });
+                i += 1;
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yomitan_import::{create_schema, import_one, CancellationToken, OnConflict, DEFAULT_BATCH_ROWS};
+
+    #[test]
+    fn long_headword_matches_longest_first() {
+        let long = "長".repeat(200);
+        let conn = Connection::open_in_memory().unwrap();
+        create_schema(&conn).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let index = serde_json::json!({ "title": "Long", "revision": "1", "format": 3 });
+        let bank = serde_json::json!([["長", "なが", "", "", 0, ["long"], 1, ""], [long, "", "", "", 0, ["very long"], 2, ""]]);
+        std::fs::write(dir.path().join("index.json"), index.to_string()).unwrap();
+        std::fs::write(dir.path().join("term_bank_1.json"), bank.to_string()).unwrap();
+        import_one(&conn, dir.path(), false, OnConflict::ReplaceOlder, DEFAULT_BATCH_ROWS, true, &CancellationToken::default(), |_| {}).unwrap();
+
+        let index = TermIndex::load(&conn).unwrap();
+        assert_eq!(index.max_len, 200);
+        let text = format!("{long}長い");
+        let segments = segment(&conn, &index, &text, false, DEFAULT_MAX_SEGMENT_CHARS).unwrap();
+        let lens: Vec<_> = segments.iter().map(|s| (s.t.chars().count(), s.matched)).collect();
+        assert_eq!(lens, [(200, true), (1, true), (1, false)]);
+    }
 
-        Ok(result_tokens)
+    #[test]
+    fn match_window_is_bounded_by_max_len() {
+        let mut index = TermIndex { nodes: vec![TrieNode::default()], max_len: 3 };
+        index.insert("ab");
+        index.insert("abcd");
+        let chars: Vec<char> = "abcdab".chars().collect();
+        // `abcd` is in the trie, but four chars are past the window
+        assert_eq!(index.longest_match(&chars, 0), Some(2));
+        index.max_len = 4;
+        assert_eq!(index.longest_match(&chars, 0), Some(4));
+        // The window also stops at the end of the text
+        assert_eq!(index.longest_match(&chars, 4), Some(2));
+        assert_eq!(index.longest_match(&chars, 5), None);
     }
 }
@@ -0,0 +1,277 @@
+use std::borrow::Cow;
+
+use unicode_normalization::char::compose;
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+/// Katakana to hiragana; everything else, including the long vowel mark, is unchanged.
+pub fn to_hiragana(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            'ァ'..='ヶ' | 'ヽ' | 'ヾ' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+/// Hiragana to katakana; everything else is unchanged.
+pub fn to_katakana(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            'ぁ'..='ゖ' | 'ゝ' | 'ゞ' => char::from_u32(c as u32 + 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+/// Unicode NFC, so a kana followed by a combining (semi-)voiced sound mark
+/// compares equal to the precomposed kana. The spacing marks ゛ and ゜ are
+/// folded in too when they follow a kana they combine with.
+pub fn normalize(s: &str) -> Cow<'_, str> {
+    if is_nfc(s) && !s.contains(['\u{309B}', '\u{309C}']) {
+        return Cow::Borrowed(s);
+    }
+    let mut out = String::with_capacity(s.len());
+    for c in s.nfc() {
+        let mark = match c {
+            '\u{309B}' => Some('\u{3099}'),
+            '\u{309C}' => Some('\u{309A}'),
+            _ => None,
+        };
+        if let Some(mark) = mark
+            && let Some(composed) = out.chars().next_back().and_then(|prev| compose(prev, mark))
+        {
+            out.pop();
+            out.push(composed);
+            continue;
+        }
+        out.push(c);
+    }
+    Cow::Owned(out)
+}
+
+/// Fullwidth ASCII to ASCII, then Latin capitals to lowercase, one char for
+/// one so folded text lines up with the original.
+pub fn fold_latin_char(c: char) -> char {
+    let c = match c {
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        _ => c,
+    };
+    if !matches!(c, 'A'..='Z' | '\u{C0}'..='\u{24F}' | '\u{1E00}'..='\u{1EFF}') {
+        return c;
+    }
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l,
+        _ => c,
+    }
+}
+
+/// `fold_latin_char` over `s`, so `ＰＣ`, `PC` and `pc` all compare equal.
+/// What `terms.term_norm` holds and term lookups match input against.
+pub fn fold_latin(s: &str) -> Cow<'_, str> {
+    if s.chars().all(|c| fold_latin_char(c) == c) {
+        return Cow::Borrowed(s);
+    }
+    Cow::Owned(s.chars().map(fold_latin_char).collect())
+}
+
+/// Hiragana of a consonant (or none) followed by each of a, i, u, e, o.
+const ROMAJI_ROWS: &[(&str, [&str; 5])] = &[
+    ("", ["あ", "い", "う", "え", "お"]),
+    ("k", ["か", "き", "く", "け", "こ"]),
+    ("g", ["が", "ぎ", "ぐ", "げ", "ご"]),
+    ("s", ["さ", "し", "す", "せ", "そ"]),
+    ("z", ["ざ", "じ", "ず", "ぜ", "ぞ"]),
+    ("t", ["た", "ち", "つ", "て", "と"]),
+    ("d", ["だ", "ぢ", "づ", "で", "ど"]),
+    ("n", ["な", "に", "ぬ", "ね", "の"]),
+    ("h", ["は", "ひ", "ふ", "へ", "ほ"]),
+    ("b", ["ば", "び", "ぶ", "べ", "ぼ"]),
+    ("p", ["ぱ", "ぴ", "ぷ", "ぺ", "ぽ"]),
+    ("m", ["ま", "み", "む", "め", "も"]),
+    ("y", ["や", "い", "ゆ", "いぇ", "よ"]),
+    ("r", ["ら", "り", "る", "れ", "ろ"]),
+    ("w", ["わ", "うぃ", "う", "うぇ", "を"]),
+    ("f", ["ふぁ", "ふぃ", "ふ", "ふぇ", "ふぉ"]),
+    ("v", ["ゔぁ", "ゔぃ", "ゔ", "ゔぇ", "ゔぉ"]),
+    ("j", ["じゃ", "じ", "じゅ", "じぇ", "じょ"]),
+    ("sh", ["しゃ", "し", "しゅ", "しぇ", "しょ"]),
+    ("ch", ["ちゃ", "ち", "ちゅ", "ちぇ", "ちょ"]),
+    ("ts", ["つぁ", "つぃ", "つ", "つぇ", "つぉ"]),
+    ("ky", ["きゃ", "きぃ", "きゅ", "きぇ", "きょ"]),
+    ("gy", ["ぎゃ", "ぎぃ", "ぎゅ", "ぎぇ", "ぎょ"]),
+    ("sy", ["しゃ", "しぃ", "しゅ", "しぇ", "しょ"]),
+    ("zy", ["じゃ", "じぃ", "じゅ", "じぇ", "じょ"]),
+    ("jy", ["じゃ", "じぃ", "じゅ", "じぇ", "じょ"]),
+    ("ty", ["ちゃ", "ちぃ", "ちゅ", "ちぇ", "ちょ"]),
+    ("cy", ["ちゃ", "ちぃ", "ちゅ", "ちぇ", "ちょ"]),
+    ("dy", ["ぢゃ", "ぢぃ", "ぢゅ", "ぢぇ", "ぢょ"]),
+    ("ny", ["にゃ", "にぃ", "にゅ", "にぇ", "にょ"]),
+    ("hy", ["ひゃ", "ひぃ", "ひゅ", "ひぇ", "ひょ"]),
+    ("by", ["びゃ", "びぃ", "びゅ", "びぇ", "びょ"]),
+    ("py", ["ぴゃ", "ぴぃ", "ぴゅ", "ぴぇ", "ぴょ"]),
+    ("my", ["みゃ", "みぃ", "みゅ", "みぇ", "みょ"]),
+    ("ry", ["りゃ", "りぃ", "りゅ", "りぇ", "りょ"]),
+    ("th", ["てゃ", "てぃ", "てゅ", "てぇ", "てょ"]),
+    ("dh", ["でゃ", "でぃ", "でゅ", "でぇ", "でょ"]),
+    ("x", ["ぁ", "ぃ", "ぅ", "ぇ", "ぉ"]),
+    ("xy", ["ゃ", "ぃ", "ゅ", "ぇ", "ょ"]),
+];
+
+/// Spellings that don't fit a row of `ROMAJI_ROWS`.
+const ROMAJI_EXTRA: &[(&str, &str)] = &[
+    ("xtsu", "っ"),
+    ("xtu", "っ"),
+    ("xwa", "ゎ"),
+    ("xka", "ゕ"),
+    ("xke", "ゖ"),
+    ("dzu", "づ"),
+    ("twu", "とぅ"),
+    ("dwu", "どぅ"),
+];
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'i' | 'u' | 'e' | 'o')
+}
+
+/// Hiragana for the romaji syllable `s`, such as `"ka"` or `"shu"`.
+fn romaji_syllable(s: &str) -> Option<&'static str> {
+    if !s.is_ascii() {
+        return None;
+    }
+    if let Some((_, kana)) = ROMAJI_EXTRA.iter().find(|(r, _)| *r == s) {
+        return Some(kana);
+    }
+    let (consonant, vowel) = s.split_at(s.len().checked_sub(1)?);
+    let v = "aiueo".find(vowel)?;
+    ROMAJI_ROWS.iter().find(|(c, _)| *c == consonant).map(|(_, row)| row[v])
+}
+
+/// Hepburn or kunrei romaji, as typed into an IME, to hiragana:
+/// - `n` is ん before a consonant, an apostrophe (`kin'en`) or the end, and
+///   `nn` there is a single ん; `m` before b or p is ん too (`shimbun`)
+/// - a doubled consonant or `tch` is っ (`kitte`, `matcha`)
+/// - long vowels are spelled out (`toukyou`, `oosaka`) or given with a macron
+///   or circumflex, where `ō` is おう and the others double their vowel;
+///   `romaji_spellings` gives the おお spelling too
+/// - `-` is ー
+///
+/// Anything else, such as kana or punctuation, passes through unchanged.
+pub fn romaji_to_kana(s: &str) -> String {
+    let mut chars = Vec::with_capacity(s.len());
+    for c in s.to_lowercase().chars() {
+        match c {
+            'ā' | 'â' => chars.extend(['a', 'a']),
+            'ī' | 'î' => chars.extend(['i', 'i']),
+            'ū' | 'û' => chars.extend(['u', 'u']),
+            'ē' | 'ê' => chars.extend(['e', 'e']),
+            'ō' | 'ô' => chars.extend(['o', 'u']),
+            _ => chars.push(c),
+        }
+    }
+    let starts_syllable = |i: usize| chars.get(i).is_some_and(|&c| is_vowel(c) || c == 'y');
+
+    let mut out = String::with_capacity(s.len() * 3);
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c == 'n' && !starts_syllable(i + 1) {
+            out.push('ん');
+            i += 1;
+            if next == Some('\'') || (next == Some('n') && !starts_syllable(i + 1)) {
+                i += 1;
+            }
+            continue;
+        }
+        if c == 'm' && matches!(next, Some('b' | 'p')) {
+            out.push('ん');
+            i += 1;
+            continue;
+        }
+        let doubled = next == Some(c) && c.is_ascii_lowercase() && !is_vowel(c);
+        if doubled || (c == 't' && next == Some('c') && chars.get(i + 2) == Some(&'h')) {
+            out.push('っ');
+            i += 1;
+            continue;
+        }
+        if c == '-' {
+            out.push('ー');
+            i += 1;
+            continue;
+        }
+        let longest = (1..=4.min(chars.len() - i)).rev().find_map(|len| {
+            let candidate: String = chars[i..i + len].iter().collect();
+            romaji_syllable(&candidate).map(|kana| (len, kana))
+        });
+        match longest {
+            Some((len, kana)) => {
+                out.push_str(kana);
+                i += len;
+            }
+            None => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// `romaji_to_kana` of `s`, then, if `s` has an `ō` or `ô`, the spelling with
+/// every one of them as おお rather than おう, as `Ōsaka` is おおさか.
+pub fn romaji_spellings(s: &str) -> Vec<String> {
+    let mut spellings = vec![romaji_to_kana(s)];
+    if s.contains(['ō', 'ô', 'Ō', 'Ô']) {
+        spellings.push(romaji_to_kana(&s.replace(['ō', 'ô', 'Ō', 'Ô'], "oo")));
+    }
+    spellings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decomposed_dakuten_matches_precomposed() {
+        let decomposed = "か\u{3099}き";
+        let precomposed = "がき";
+        assert_eq!(normalize(decomposed), precomposed);
+        assert_eq!(normalize(precomposed), normalize(decomposed));
+        assert!(matches!(normalize(precomposed), Cow::Borrowed(_)));
+        assert_eq!(normalize("ハ\u{309A}ン"), "パン");
+        assert_eq!(normalize("パン"), normalize("ハ\u{309A}ン"));
+    }
+
+    #[test]
+    fn spacing_marks_fold_only_onto_kana_they_combine_with() {
+        assert_eq!(normalize("か゛"), "が");
+        assert_eq!(normalize("ウ゛ァ"), "ヴァ");
+        assert_eq!(normalize("ほ゜"), "ぽ");
+        // Nothing to combine with: the mark stays as written
+        for plain in ["゛", "あ゛", "ん゜", "A゛B", "「゛」", "濁点の゛です"] {
+            assert_eq!(normalize(plain), plain);
+        }
+        assert!(matches!(normalize("日本語のテキスト、ABC。"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn romaji_resolves_ambiguous_spellings() {
+        for (romaji, kana) in [
+            ("kin'en", "きんえん"),
+            ("konnichiwa", "こんにちわ"),
+            ("kon'ya", "こんや"),
+            ("konya", "こにゃ"),
+            ("shimbun", "しんぶん"),
+            ("matcha", "まっちゃ"),
+            ("honn", "ほん"),
+            ("toukyou", "とうきょう"),
+            ("oosaka", "おおさか"),
+        ] {
+            assert_eq!(romaji_to_kana(romaji), kana, "{romaji}");
+        }
+        assert_eq!(romaji_spellings("Ōsaka"), ["おうさか", "おおさか"]);
+        assert_eq!(romaji_spellings("tôkyô"), ["とうきょう", "とおきょお"]);
+        assert_eq!(romaji_spellings("taberu"), ["たべる"]);
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+
+use rusqlite::{params, Connection, Row};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::kana::{normalize, to_hiragana};
+use crate::term_meta::{parse_freq, FreqInfo};
+
+#[derive(Serialize)]
+pub struct KanjiEntry {
+    pub character: String,
+    pub onyomi: Vec<String>,
+    pub kunyomi: Vec<String>,
+    pub tags: Vec<String>,
+    pub meanings: Vec<String>,
+    pub stats: Map<String, Value>,
+    pub dict_title: String,
+    pub frequencies: Vec<FreqInfo>,
+}
+
+/// Columns selected by every kanji query; keep in sync with `KanjiEntry::from_row`.
+const KANJI_SELECT: &str = "
+    SELECT k.kanji, k.onyomi, k.kunyomi, k.tags, k.meanings, k.stats, COALESCE(d.display_title, d.title)
+    FROM kanji k
+    JOIN dictionaries d ON d.id = k.dict_id AND d.enabled = 1";
+
+impl KanjiEntry {
+    fn from_row(r: &Row) -> rusqlite::Result<Self> {
+        let meanings: String = r.get(4)?;
+        let stats: String = r.get(5)?;
+        Ok(KanjiEntry {
+            character: r.get(0)?,
+            onyomi: split(r.get(1)?),
+            kunyomi: split(r.get(2)?),
+            tags: split(r.get(3)?),
+            meanings: serde_json::from_str(&meanings).unwrap_or_default(),
+            stats: serde_json::from_str(&stats).unwrap_or_default(),
+            dict_title: r.get(6)?,
+            frequencies: vec![],
+        })
+    }
+}
+
+fn split(s: Option<String>) -> Vec<String> {
+    s.unwrap_or_default().split_whitespace().map(str::to_string).collect()
+}
+
+/// `freq` rows of `kanji_meta` for `character` from enabled dictionaries.
+fn kanji_frequencies(conn: &Connection, character: &str) -> rusqlite::Result<Vec<FreqInfo>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT COALESCE(d.display_title, d.title), m.data
+         FROM kanji_meta m
+         JOIN dictionaries d ON d.id = m.dict_id AND d.enabled = 1
+         WHERE m.character = ?1 AND m.mode = 'freq'
+         ORDER BY d.sort_order, m.id",
+    )?;
+    let rows = stmt.query_map(params![character], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)))?;
+    let mut out = Vec::new();
+    for row in rows {
+        let (dict_title, data) = row?;
+        if let Some((value, display)) = serde_json::from_str(&data).ok().as_ref().and_then(parse_freq) {
+            out.push(FreqInfo { dict_title, value, display });
+        }
+    }
+    Ok(out)
+}
+
+/// Fill in each entry's frequencies. Frequency lists rank the character
+/// itself, so every entry for a character shares them.
+fn attach_frequencies(conn: &Connection, entries: &mut [KanjiEntry]) -> rusqlite::Result<()> {
+    let mut by_character: HashMap<String, Vec<FreqInfo>> = HashMap::new();
+    for e in entries {
+        if !by_character.contains_key(&e.character) {
+            by_character.insert(e.character.clone(), kanji_frequencies(conn, &e.character)?);
+        }
+        e.frequencies = by_character[&e.character].clone();
+    }
+    Ok(())
+}
+
+/// Every enabled dictionary's entry for `character`, in dictionary priority order.
+pub fn get_kanji(conn: &Connection, character: &str) -> rusqlite::Result<Vec<KanjiEntry>> {
+    let sql = format!("{KANJI_SELECT} WHERE k.kanji = ?1 ORDER BY d.sort_order, k.id");
+    let mut stmt = conn.prepare_cached(&sql)?;
+    let mut out = stmt.query_map(params![character], KanjiEntry::from_row)?.collect::<rusqlite::Result<Vec<_>>>()?;
+    attach_frequencies(conn, &mut out)?;
+    Ok(out)
+}
+
+/// Whether `reading`, in hiragana, is one of the space-separated `onyomi`
+/// or `kunyomi`. Kun'yomi match with or without their okurigana (`た.べる`
+/// is read both たべる and た), and the `-` marking prefixes and suffixes is ignored.
+fn has_reading(onyomi: &str, kunyomi: &str, reading: &str) -> bool {
+    onyomi.split_whitespace().any(|on| to_hiragana(on.trim_matches('-')) == reading)
+        || kunyomi.split_whitespace().map(|kun| kun.trim_matches('-')).any(|kun| {
+            let stem = kun.split('.').next().unwrap_or(kun);
+            stem == reading || kun.replace('.', "") == reading
+        })
+}
+
+/// Kanji with `reading` for an on'yomi or kun'yomi, in either kana script
+/// and with or without the dot before okurigana.
+pub fn lookup_kanji_by_reading(conn: &Connection, reading: &str) -> rusqlite::Result<Vec<KanjiEntry>> {
+    let reading = to_hiragana(&normalize(reading.trim())).replace('.', "");
+    if reading.is_empty() {
+        return Ok(vec![]);
+    }
+    // Readings are split and normalized here rather than in SQL, so filter
+    // on the small columns first and only parse the entries that match
+    let mut select = conn.prepare_cached(
+        "SELECT k.id, COALESCE(k.onyomi, ''), COALESCE(k.kunyomi, '')
+         FROM kanji k
+         JOIN dictionaries d ON d.id = k.dict_id AND d.enabled = 1
+         ORDER BY d.sort_order, k.id",
+    )?;
+    let mut ids = Vec::new();
+    let mut rows = select.query([])?;
+    while let Some(r) = rows.next()? {
+        let (onyomi, kunyomi): (String, String) = (r.get(1)?, r.get(2)?);
+        if has_reading(&onyomi, &kunyomi, &reading) {
+            ids.push(r.get::<_, i64>(0)?);
+        }
+    }
+
+    let sql = format!("{KANJI_SELECT} WHERE k.id = ?1");
+    let mut stmt = conn.prepare_cached(&sql)?;
+    let mut out = ids
+        .iter()
+        .map(|id| stmt.query_row(params![id], KanjiEntry::from_row))
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    attach_frequencies(conn, &mut out)?;
+    Ok(out)
+}
+
+/// Kanji whose `stats` give `count` strokes, as KANJIDIC-based dictionaries do.
+pub fn lookup_kanji_by_strokes(conn: &Connection, count: i64) -> rusqlite::Result<Vec<KanjiEntry>> {
+    let sql = format!(
+        "{KANJI_SELECT} WHERE CAST(json_extract(k.stats, '$.strokes') AS INTEGER) = ?1 ORDER BY d.sort_order, k.id"
+    );
+    let mut stmt = conn.prepare_cached(&sql)?;
+    let mut out = stmt.query_map(params![count], KanjiEntry::from_row)?.collect::<rusqlite::Result<Vec<_>>>()?;
+    attach_frequencies(conn, &mut out)?;
+    Ok(out)
+}
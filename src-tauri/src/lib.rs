@@ -1,28 +1,58 @@
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
 
-use tauri::Manager;
+use tauri::{Emitter, Manager};
 
-use crate::ja_tokenize::{LinderaAnalyzer, SegmentResult};
+use crate::ja_tokenize::{SegmentResult, TermIndex};
 use rusqlite::{params, Connection};
 use serde::Serialize;
 use tauri::AppHandle;
 
+mod content;
+mod custom;
+mod deinflect;
+mod dictionaries;
+mod export;
+mod glossary;
+mod kana;
+mod kanji;
+mod lookup;
+mod remote;
+mod styles;
+mod tags;
+mod term_meta;
+mod usage;
 mod yomitan_import;
 
-use yomitan_import::{find_resources_dir, import_bundled_zips};
+use deinflect::Deinflector;
+use dictionaries::{DbStats, DictDetail, DictInfo, IntegrityReport, PendingImport};
+use kana::{fold_latin, normalize, romaji_spellings};
+use kanji::KanjiEntry;
+use lookup::{GroupedEntry, Limited, TermBrief, TermEntry};
+use remote::UpdateInfo;
+use usage::UsageTracker;
+use yomitan_import::{adopt_bundled_zips, create_indexes, find_resources_dir, finish_import, import_bundled_zips, import_one, open_db, CancellationToken, DbOptions, DEFAULT_BATCH_ROWS, ImportAction, ImportError, ImportSummary, OnConflict, Progress};
 
 pub mod ja_tokenize;
 
+/// For `benches/import.rs`, which times glossary hashing on its own.
+#[doc(hidden)]
+pub use yomitan_import::sha1_hex;
+
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
 fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
+/// `max_chars` defaults to `ja_tokenize::DEFAULT_MAX_SEGMENT_CHARS`; longer text is an error.
 #[tauri::command]
-fn segment(text: &str, state: tauri::State<MyState>) -> Result<Vec<SegmentResult>, ()> {
-    let result = state.lindera.analyze(text);
-    Ok(result.unwrap())
+fn segment(text: &str, with_definitions: bool, max_chars: Option<usize>, state: tauri::State<MyState>) -> Result<Vec<SegmentResult>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    let index = state.term_index.read().map_err(|e| e.to_string())?;
+    let max_chars = max_chars.unwrap_or(ja_tokenize::DEFAULT_MAX_SEGMENT_CHARS);
+    ja_tokenize::segment(&conn, &index, text, with_definitions, max_chars).map_err(|e| e.to_string())
 }
 
 #[derive(Serialize)]
@@ -33,20 +63,15 @@ struct SearchRow {
     rules: String,
     score: i64,
     glossary_json: String,
+    content_type: String,
     sequence: Option<i64>,
     term_tags: String,
     dict_title: String,
 }
 
 #[tauri::command]
-fn search_terms(app: AppHandle, q_term: String, q_reading: String, limit: u32, offset: u32) -> Result<Vec<SearchRow>, String> {
-    let cfg_dir = app.path().config_dir();
-    let app_dir = cfg_dir
-        .unwrap_or_else(|_| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")))
-        .join("cc.polv.cjdic");
-    let _ = std::fs::create_dir_all(&app_dir);
-    let db_path = app_dir.join("yomitan.db");
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+fn search_terms(q_term: String, q_reading: String, limit: u32, offset: u32, state: tauri::State<MyState>) -> Result<Vec<SearchRow>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
 
     let sql = r#"
       SELECT
@@ -55,24 +80,25 @@ fn search_terms(app: AppHandle, q_term: String, q_reading: String, limit: u32, o
         COALESCE(dt.tags,  '')  AS def_tags,
         COALESCE(r.rules,  '')  AS rules,
         t.score,
-        g.content               AS glossary_json,
+        COALESCE(g.content, t.glossary) AS glossary_json,
         t.sequence,
         COALESCE(tt.tags,  '')  AS term_tags,
-        d.title                 AS dict_title
+        COALESCE(d.display_title, d.title) AS dict_title,
+        COALESCE(g.content_type, t.glossary_content_type, 'text') AS content_type
       FROM terms t
-      JOIN  glossaries    g  ON g.id  = t.glossary_id
-      JOIN  dictionaries  d  ON d.id  = t.dict_id
+      LEFT JOIN glossaries    g  ON g.id  = t.glossary_id
+      JOIN  dictionaries  d  ON d.id  = t.dict_id AND d.enabled = 1
       LEFT JOIN def_tag_sets  dt ON dt.id = t.def_tags_id
       LEFT JOIN rule_sets      r ON r.id  = t.rules_id
       LEFT JOIN term_tag_sets tt ON tt.id = t.term_tags_id
-      WHERE t.term LIKE ?1 ESCAPE '\' OR t.reading LIKE ?2 ESCAPE '\'
-      ORDER BY t.score DESC
+      WHERE t.term LIKE ?1 ESCAPE '\' OR t.term_norm LIKE ?1 ESCAPE '\' OR t.reading LIKE ?2 ESCAPE '\'
+      ORDER BY t.score + d.score_offset DESC
       LIMIT ?3 OFFSET ?4
     "#;
 
     let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
     let rows = stmt
-        .query_map(params![q_term, q_reading, limit, offset], |r| {
+        .query_map(params![fold_latin(&normalize(&q_term)), normalize(&q_reading), limit, offset], |r| {
             Ok(SearchRow {
                 term: r.get(0)?,
                 reading: r.get(1)?,
@@ -80,6 +106,7 @@ fn search_terms(app: AppHandle, q_term: String, q_reading: String, limit: u32, o
                 rules: r.get(3)?,
                 score: r.get(4)?,
                 glossary_json: r.get(5)?,
+                content_type: r.get(9)?,
                 sequence: r.get(6)?,
                 term_tags: r.get(7)?,
                 dict_title: r.get(8)?,
@@ -94,8 +121,627 @@ fn search_terms(app: AppHandle, q_term: String, q_reading: String, limit: u32, o
     Ok(out)
 }
 
+#[tauri::command]
+fn lookup_term(term: String, limit: Option<usize>, state: tauri::State<MyState>) -> Result<Limited<TermEntry>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    let found = lookup::lookup_term(&conn, &state.deinflector, &term, limit.unwrap_or(lookup::DEFAULT_LIMIT)).map_err(|e| e.to_string())?;
+    state.usage.record(found.entries.iter().map(|e| e.dict_id));
+    Ok(found)
+}
+
+#[tauri::command]
+fn lookup_grouped(term: String, state: tauri::State<MyState>) -> Result<Vec<GroupedEntry>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    let groups = lookup::lookup_grouped(&conn, &state.deinflector, &term).map_err(|e| e.to_string())?;
+    state.usage.record(groups.iter().map(|g| g.dict_id));
+    Ok(groups)
+}
+
+#[tauri::command]
+fn lookup_terms(terms: Vec<String>, state: tauri::State<MyState>) -> Result<HashMap<String, Vec<TermEntry>>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    let found = lookup::lookup_surfaces(&conn, &terms).map_err(|e| e.to_string())?;
+    state.usage.record(found.values().flatten().map(|e| e.dict_id));
+    Ok(found)
+}
+
+#[tauri::command]
+fn lookup_by_reading(reading: String, state: tauri::State<MyState>) -> Result<Vec<TermEntry>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    let found = lookup::lookup_by_reading(&conn, &reading).map_err(|e| e.to_string())?;
+    state.usage.record(found.iter().map(|e| e.dict_id));
+    Ok(found)
+}
+
+/// Readings of headword `term` across dictionaries, most common first, for furigana.
+#[tauri::command]
+fn readings_for(term: String, state: tauri::State<MyState>) -> Result<Vec<String>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    lookup::readings_for(&conn, &term).map_err(|e| e.to_string())
+}
+
+/// Reading lookup for input typed in romaji, e.g. `taberu`. `Ōsaka` is
+/// looked up as both おうさか and おおさか.
+#[tauri::command]
+fn lookup_romaji(romaji: String, state: tauri::State<MyState>) -> Result<Vec<TermEntry>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    let mut found = Vec::new();
+    let mut seen = HashSet::new();
+    for kana in romaji_spellings(&romaji) {
+        let entries = lookup::lookup_by_reading(&conn, &kana).map_err(|e| e.to_string())?;
+        found.extend(entries.into_iter().filter(|e| seen.insert(e.id)));
+    }
+    state.usage.record(found.iter().map(|e| e.dict_id));
+    Ok(found)
+}
+
+#[tauri::command]
+fn lookup_by_sequence(dict_id: i64, sequence: i64, state: tauri::State<MyState>) -> Result<Vec<TermEntry>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    lookup::lookup_by_sequence(&conn, dict_id, sequence).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn search_prefix_brief(prefix: String, limit: usize, offset: Option<usize>, wildcards: bool, state: tauri::State<MyState>) -> Result<Vec<TermBrief>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    let found = lookup::search_prefix_brief(&conn, &prefix, limit, offset.unwrap_or(0), wildcards).map_err(|e| e.to_string())?;
+    state.usage.record(found.iter().map(|b| b.dict_id));
+    Ok(found)
+}
+
+#[tauri::command]
+fn get_glossary(hash: String, state: tauri::State<MyState>) -> Result<serde_json::Value, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    lookup::get_glossary(&conn, &hash)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("no glossary with hash {}", hash))
+}
+
+#[tauri::command]
+fn get_term_glossary(id: i64, state: tauri::State<MyState>) -> Result<serde_json::Value, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    lookup::term_glossary(&conn, id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("no term with id {}", id))
+}
+
+#[tauri::command]
+fn suggest(query: String, max_distance: u32, limit: usize, state: tauri::State<MyState>) -> Result<Vec<TermEntry>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    let found = lookup::suggest(&conn, &query, max_distance as usize, limit).map_err(|e| e.to_string())?;
+    state.usage.record(found.iter().map(|e| e.dict_id));
+    Ok(found)
+}
+
+#[tauri::command]
+fn terms_sharing_glossary(term: String, state: tauri::State<MyState>) -> Result<Vec<TermEntry>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    lookup::terms_sharing_glossary(&conn, &term).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn lookup_by_tag(dict_id: i64, tag: String, state: tauri::State<MyState>) -> Result<Vec<TermEntry>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    lookup::lookup_by_tag(&conn, dict_id, &tag).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn search_glossary(query: String, state: tauri::State<MyState>) -> Result<Vec<TermEntry>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    let found = lookup::search_glossary(&conn, &query).map_err(|e| e.to_string())?;
+    state.usage.record(found.iter().map(|e| e.dict_id));
+    Ok(found)
+}
+
+#[tauri::command]
+fn get_kanji(character: String, state: tauri::State<MyState>) -> Result<Vec<KanjiEntry>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    kanji::get_kanji(&conn, &character).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn lookup_kanji_by_reading(reading: String, state: tauri::State<MyState>) -> Result<Vec<KanjiEntry>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    kanji::lookup_kanji_by_reading(&conn, &reading).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn lookup_kanji_by_strokes(count: i64, state: tauri::State<MyState>) -> Result<Vec<KanjiEntry>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    kanji::lookup_kanji_by_strokes(&conn, count).map_err(|e| e.to_string())
+}
+
+/// `offset` (term, reading) pairs are skipped first, for paging; it defaults to 0.
+#[tauri::command]
+fn search_prefix(prefix: String, limit: Option<usize>, offset: Option<usize>, wildcards: bool, state: tauri::State<MyState>) -> Result<Limited<TermEntry>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    let found = lookup::search_prefix(&conn, &prefix, limit.unwrap_or(lookup::DEFAULT_LIMIT), offset.unwrap_or(0), wildcards).map_err(|e| e.to_string())?;
+    state.usage.record(found.entries.iter().map(|e| e.dict_id));
+    Ok(found)
+}
+
+/// The number of results `search_prefix` pages through, for page controls.
+#[tauri::command]
+fn count_prefix(prefix: String, wildcards: Option<bool>, state: tauri::State<MyState>) -> Result<i64, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    lookup::count_prefix(&conn, &prefix, wildcards.unwrap_or(false)).map_err(|e| e.to_string())
+}
+
+// Runs off the main thread so `cancel_import` can be handled meanwhile
+#[tauri::command(async)]
+fn import_dictionary(
+    app: AppHandle,
+    path: String,
+    on_conflict: OnConflict,
+    batch_rows: Option<usize>,
+    intern_glossaries: Option<bool>,
+    vacuum: Option<bool>,
+    state: tauri::State<MyState>,
+) -> Result<ImportSummary, ImportError> {
+    import_path(&app, &state, Path::new(&path), on_conflict, batch_rows, intern_glossaries, vacuum)
+}
+
+/// Download the zip at `url` and import it as `import_dictionary` does. The
+/// download goes to a temporary file, deleted once the import is over.
+#[tauri::command]
+async fn import_from_url(
+    app: AppHandle,
+    url: String,
+    on_conflict: OnConflict,
+    batch_rows: Option<usize>,
+    intern_glossaries: Option<bool>,
+    vacuum: Option<bool>,
+) -> Result<ImportSummary, ImportError> {
+    let file = remote::download_zip(&url).await?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<MyState>();
+        import_path(&app, &state, file.path(), on_conflict, batch_rows, intern_glossaries, vacuum)
+    })
+    .await
+    .map_err(|e| ImportError::Db(e.into()))?
+}
+
+/// The body of `import_dictionary`, shared with `import_from_url`.
+fn import_path(
+    app: &AppHandle,
+    state: &MyState,
+    path: &Path,
+    on_conflict: OnConflict,
+    batch_rows: Option<usize>,
+    intern_glossaries: Option<bool>,
+    vacuum: Option<bool>,
+) -> Result<ImportSummary, ImportError> {
+    let conn = state.db.lock().map_err(|e| ImportError::Db(e.to_string().into()))?;
+    state.import_cancel.reset();
+    let batch_rows = batch_rows.unwrap_or(DEFAULT_BATCH_ROWS);
+    let intern_glossaries = intern_glossaries.unwrap_or(true);
+    let summary = import_one(&conn, path, false, on_conflict, batch_rows, intern_glossaries, &state.import_cancel, emit_progress(app))?;
+    if matches!(summary.action, ImportAction::Imported | ImportAction::Replaced) {
+        {
+            // No lookup may be reading while `finish_import` checkpoints the WAL
+            let _reader = state.reader.lock().map_err(|e| ImportError::Db(e.to_string().into()))?;
+            finish_import(&conn, vacuum.unwrap_or(false))?;
+        }
+        state.reload_term_index(&conn).map_err(|e| ImportError::Db(e.into()))?;
+    }
+    let _ = app.emit("import://complete", vec![summary.clone()]);
+    Ok(summary)
+}
+
+#[tauri::command]
+fn add_custom_term(term: String, reading: String, definition: String, state: tauri::State<MyState>) -> Result<i64, String> {
+    let conn = state.db.lock().map_err(|e| e.to_string())?;
+    let id = custom::add_term(&conn, &term, &reading, &definition).map_err(|e| e.to_string())?;
+    state.reload_term_index(&conn)?;
+    Ok(id)
+}
+
+#[tauri::command]
+fn remove_custom_term(id: i64, state: tauri::State<MyState>) -> Result<(), String> {
+    let conn = state.db.lock().map_err(|e| e.to_string())?;
+    custom::remove_term(&conn, id).map_err(|e| e.to_string())?;
+    state.reload_term_index(&conn)
+}
+
+/// Ask a running `import_dictionary` to stop after the batch of rows it is loading.
+#[tauri::command]
+fn cancel_import(state: tauri::State<MyState>) {
+    state.import_cancel.cancel();
+}
+
+/// Forward import progress to the frontend as `import://progress` events.
+fn emit_progress(app: &AppHandle) -> impl FnMut(Progress) + '_ {
+    move |p| {
+        let _ = app.emit("import://progress", p);
+    }
+}
+
+#[tauri::command]
+fn list_dictionaries(state: tauri::State<MyState>) -> Result<Vec<DictInfo>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    dictionaries::list(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn dictionary_detail(id: i64, state: tauri::State<MyState>) -> Result<DictDetail, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    dictionaries::detail(&conn, id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn is_installed(title: String, revision: String, state: tauri::State<MyState>) -> Result<bool, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    dictionaries::is_installed(&conn, &title, &revision).map_err(|e| e.to_string())
+}
+
+/// The newest installed revision of `title`, to offer updating to a newer one.
+#[tauri::command]
+fn is_title_installed(title: String, state: tauri::State<MyState>) -> Result<Option<String>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    dictionaries::installed_revision(&conn, &title).map_err(|e| e.to_string())
+}
+
+/// Which updatable dictionaries have a newer revision at their `indexUrl`.
+/// Only each remote index.json is fetched; updating is left to `import_from_url`.
+#[tauri::command]
+async fn check_updates(state: tauri::State<'_, MyState>) -> Result<Vec<UpdateInfo>, String> {
+    let updatable = {
+        let conn = state.reader.lock().map_err(|e| e.to_string())?;
+        dictionaries::updatable(&conn).map_err(|e| e.to_string())?
+    };
+    let checks: Vec<_> = updatable.into_iter().map(|d| tauri::async_runtime::spawn(remote::check_update(d))).collect();
+    let mut updates = Vec::with_capacity(checks.len());
+    for check in checks {
+        updates.push(check.await.map_err(|e| e.to_string())?);
+    }
+    Ok(updates)
+}
+
+/// CSS for the dictionary's structured content; it only applies inside an
+/// element with `data-dictionary-id` set to `id`.
+#[tauri::command]
+fn get_dictionary_styles(id: i64, state: tauri::State<MyState>) -> Result<Option<String>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    dictionaries::styles(&conn, id).map_err(|e| e.to_string())
+}
+
+/// A file a glossary image's `path` names in dictionary `dict_id`, sent as
+/// raw bytes rather than a JSON array of them.
+#[tauri::command]
+fn get_media(dict_id: i64, path: String, state: tauri::State<MyState>) -> Result<tauri::ipc::Response, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    dictionaries::media(&conn, dict_id, &path).map(tauri::ipc::Response::new).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn db_stats(state: tauri::State<MyState>) -> Result<DbStats, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    dictionaries::stats(&conn).map_err(|e| e.to_string())
+}
+
+/// `PRAGMA integrity_check` and `foreign_key_check`, for after a crash. Reads
+/// every page, so it runs off the main thread on a connection of its own
+/// rather than holding `reader` from lookups.
+#[tauri::command(async)]
+fn check_integrity(state: tauri::State<'_, MyState>) -> Result<IntegrityReport, String> {
+    let db_path = state.profile.read().map_err(|e| e.to_string())?.db_path.clone();
+    let conn = open_db(&db_path, &DbOptions::query_only()).map_err(|e| e.to_string())?;
+    dictionaries::check_integrity(&conn).map_err(|e| e.to_string())
+}
+
+/// Delete the rows `check_integrity` reported as orphans.
+#[tauri::command]
+fn remove_orphans(state: tauri::State<MyState>) -> Result<usize, String> {
+    let mut conn = state.db.lock().map_err(|e| e.to_string())?;
+    let removed = dictionaries::remove_orphans(&mut conn).map_err(|e| e.to_string())?;
+    state.reload_term_index(&conn)?;
+    Ok(removed)
+}
+
+/// `VACUUM` rewrites the whole file, so it runs off the main thread.
+#[tauri::command(async)]
+fn compact_database(state: tauri::State<'_, MyState>) -> Result<i64, String> {
+    let conn = state.db.lock().map_err(|e| e.to_string())?;
+    dictionaries::compact(&conn).map_err(|e| e.to_string())
+}
+
+/// Delete every dictionary. `confirm` must be `dictionaries::RESET_CONFIRMATION`.
+#[tauri::command]
+fn reset_database(confirm: String, keep_created_at: bool, state: tauri::State<MyState>) -> Result<(), String> {
+    let conn = state.db.lock().map_err(|e| e.to_string())?;
+    dictionaries::reset(&conn, &confirm, keep_created_at).map_err(|e| e.to_string())?;
+    state.reload_term_index(&conn)
+}
+
+/// One of `yomitan_import::JOURNAL_MODES`; applies from the next launch.
+#[tauri::command]
+fn set_journal_mode(mode: String, state: tauri::State<MyState>) -> Result<(), String> {
+    let conn = state.db.lock().map_err(|e| e.to_string())?;
+    yomitan_import::set_journal_mode(&conn, &mode).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_dictionary_title(id: i64, title: String, state: tauri::State<MyState>) -> Result<(), String> {
+    let conn = state.db.lock().map_err(|e| e.to_string())?;
+    dictionaries::set_title(&conn, id, &title).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_dictionary_enabled(id: i64, enabled: bool, state: tauri::State<MyState>) -> Result<(), String> {
+    let conn = state.db.lock().map_err(|e| e.to_string())?;
+    dictionaries::set_enabled(&conn, id, enabled).map_err(|e| e.to_string())?;
+    state.reload_term_index(&conn)
+}
+
+#[tauri::command]
+fn set_dictionary_score_offset(id: i64, score_offset: i64, state: tauri::State<MyState>) -> Result<(), String> {
+    let conn = state.db.lock().map_err(|e| e.to_string())?;
+    dictionaries::set_score_offset(&conn, id, score_offset).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn reorder_dictionaries(ordered_ids: Vec<i64>, state: tauri::State<MyState>) -> Result<(), String> {
+    let mut conn = state.db.lock().map_err(|e| e.to_string())?;
+    dictionaries::reorder(&mut conn, &ordered_ids).map_err(|e| e.to_string())
+}
+
+/// `entries_per_bank` defaults to `export::DEFAULT_ENTRIES_PER_BANK`. Reads
+/// through a connection of its own, as `export_jsonl` does.
+#[tauri::command(async)]
+fn export_dictionary(id: i64, out_path: String, entries_per_bank: Option<usize>, state: tauri::State<'_, MyState>) -> Result<(), String> {
+    let db_path = state.profile.read().map_err(|e| e.to_string())?.db_path.clone();
+    let conn = open_db(&db_path, &DbOptions::query_only()).map_err(|e| e.to_string())?;
+    let entries_per_bank = entries_per_bank.unwrap_or(export::DEFAULT_ENTRIES_PER_BANK);
+    export::export_dictionary(&conn, id, Path::new(&out_path), entries_per_bank).map_err(|e| format!("{:#}", e))
+}
+
+/// Every term, or only dictionary `dict_id`'s, as JSON Lines for analysis
+/// outside the app. Returns the number of lines written. A full export runs
+/// long, so it reads through a connection of its own rather than holding
+/// `reader` from lookups.
+#[tauri::command(async)]
+fn export_jsonl(out_path: String, dict_id: Option<i64>, state: tauri::State<'_, MyState>) -> Result<usize, String> {
+    let db_path = state.profile.read().map_err(|e| e.to_string())?.db_path.clone();
+    let conn = open_db(&db_path, &DbOptions::query_only()).map_err(|e| e.to_string())?;
+    export::export_jsonl(&conn, dict_id, Path::new(&out_path)).map_err(|e| format!("{:#}", e))
+}
+
+/// Move dictionary `drop_id`'s rows into `keep_id` and delete `drop_id`.
+#[tauri::command]
+fn merge_dictionaries(keep_id: i64, drop_id: i64, state: tauri::State<MyState>) -> Result<usize, String> {
+    let mut conn = state.db.lock().map_err(|e| e.to_string())?;
+    let moved = dictionaries::merge(&mut conn, keep_id, drop_id).map_err(|e| e.to_string())?;
+    state.reload_term_index(&conn)?;
+    Ok(moved)
+}
+
+#[tauri::command]
+fn uninstall_dictionary(id: i64, state: tauri::State<MyState>) -> Result<usize, String> {
+    let mut conn = state.db.lock().map_err(|e| e.to_string())?;
+    let reclaimed = dictionaries::uninstall(&mut conn, id).map_err(|e| e.to_string())?;
+    state.reload_term_index(&conn)?;
+    Ok(reclaimed)
+}
+
+/// Imports cut short by a crash, which importing the same file again resumes.
+#[tauri::command]
+fn list_pending_imports(state: tauri::State<MyState>) -> Result<Vec<PendingImport>, String> {
+    let conn = state.reader.lock().map_err(|e| e.to_string())?;
+    dictionaries::pending_imports(&conn).map_err(|e| e.to_string())
+}
+
+/// Delete pending import `id` instead of resuming it. Its rows were never
+/// enabled, so the segmentation index is unaffected.
+#[tauri::command]
+fn discard_pending_import(id: i64, state: tauri::State<MyState>) -> Result<usize, String> {
+    let mut conn = state.db.lock().map_err(|e| e.to_string())?;
+    dictionaries::discard_pending(&mut conn, id).map_err(|e| e.to_string())
+}
+
 struct MyState {
-    lindera: LinderaAnalyzer,
+    term_index: RwLock<TermIndex>,
+    deinflector: Deinflector,
+    db: Mutex<Connection>,
+    /// Query-only connection for lookups, so they read the last committed
+    /// state instead of waiting out an import on `db`.
+    reader: Mutex<Connection>,
+    /// Not behind `db`, which the running import holds.
+    import_cancel: CancellationToken,
+    /// Dictionaries lookups returned entries from, written out by `flush_usage`.
+    usage: UsageTracker,
+    /// The profile `db` and `reader` are open on.
+    profile: RwLock<Profile>,
+    /// Where the bundled dictionaries imported into each profile live, if found.
+    resources_dir: Option<PathBuf>,
+}
+
+/// A named dictionary set with a database of its own, so one language's
+/// dictionaries stay out of another's lookups.
+struct Profile {
+    name: String,
+    db_path: PathBuf,
+}
+
+impl MyState {
+    /// Rebuild the segmentation index after the set of enabled dictionaries changes.
+    /// Callers hold the `db` lock and load through it; `term_index` is taken
+    /// last, as `segment` takes it after `reader`, so neither waits on the other.
+    fn reload_term_index(&self, conn: &Connection) -> Result<(), String> {
+        let index = TermIndex::load(conn).map_err(|e| e.to_string())?;
+        *self.term_index.write().map_err(|e| e.to_string())? = index;
+        Ok(())
+    }
+}
+
+/// How often lookups' dictionary use is written to the database.
+const USAGE_FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Write the dictionary use lookups noted. A running import holding `db`
+/// puts it off to the next interval rather than queueing behind it.
+fn flush_usage(app: &AppHandle) {
+    let Some(state) = app.try_state::<MyState>() else { return };
+    if let Ok(conn) = state.db.try_lock()
+        && let Err(e) = state.usage.flush(&conn)
+    {
+        eprintln!("recording dictionary use failed: {}", e);
+    }
+}
+
+/// Import the bundled dictionaries not imported before into the active
+/// profile, then refresh as `import_dictionary` does.
+fn import_bundled(app: &AppHandle, resources_dir: &Path) -> Result<(), String> {
+    let state = app.state::<MyState>();
+    let conn = state.db.lock().map_err(|e| e.to_string())?;
+    state.import_cancel.reset();
+    let summaries = import_bundled_zips(&conn, resources_dir, &state.import_cancel, emit_progress(app)).map_err(|e| e.to_string())?;
+    if summaries.iter().any(|s| matches!(s.action, ImportAction::Imported | ImportAction::Replaced)) {
+        {
+            let _reader = state.reader.lock().map_err(|e| e.to_string())?;
+            finish_import(&conn, false).map_err(|e| e.to_string())?;
+        }
+        state.reload_term_index(&conn)?;
+    }
+    if !summaries.is_empty() {
+        let _ = app.emit("import://complete", summaries);
+    }
+    Ok(())
+}
+
+/// Per-user app directory holding `yomitan.db` and `profiles/`.
+fn app_dir(app: &AppHandle) -> PathBuf {
+    app.path()
+        .config_dir()
+        .unwrap_or_else(|_| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")))
+        .join("cc.polv.cjdic")
+}
+
+/// The profile in use before any was chosen, kept in `yomitan.db`.
+const DEFAULT_PROFILE: &str = "default";
+
+/// File in the app directory naming the profile to open at launch.
+const PROFILE_FILE: &str = "profile";
+
+/// `yomitan.db` for the default profile, otherwise `profiles/<name>.db`.
+/// Names are letters, digits, `-` and `_` so they can't leave `profiles/`.
+fn profile_db_path(app_dir: &Path, name: &str) -> Result<PathBuf, String> {
+    if name == DEFAULT_PROFILE {
+        return Ok(app_dir.join("yomitan.db"));
+    }
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("invalid profile name {:?}", name));
+    }
+    Ok(app_dir.join("profiles").join(format!("{}.db", name)))
+}
+
+/// The write connection and query-only reader on a profile's database,
+/// creating and migrating it first if needed.
+fn open_profile(db_path: &Path, resources_dir: Option<&Path>) -> rusqlite::Result<(Connection, Connection)> {
+    if let Some(dir) = db_path.parent()
+        && let Err(e) = std::fs::create_dir_all(dir)
+    {
+        eprintln!("failed to create {}: {}", dir.display(), e);
+    }
+    let first_run = !db_path.exists();
+    // It creates and migrates the schema, so it is opened before the reader
+    let conn = open_db(db_path, &DbOptions::default())?;
+    let reader = open_db(db_path, &DbOptions::query_only())?;
+    if let Some(res) = resources_dir
+        && !first_run
+        && let Err(e) = adopt_bundled_zips(&conn, res)
+    {
+        eprintln!("recording bundled dictionaries failed: {}", e);
+    }
+    // No-op once present; covers databases imported before these indexes existed
+    create_indexes(&conn)?;
+    Ok((conn, reader))
+}
+
+/// Import the bundled dictionaries into the active profile off the main
+/// thread, so the window stays responsive; lookups see what was installed
+/// before until it lands.
+fn spawn_import_bundled(app: &AppHandle) {
+    let Some(res) = app.state::<MyState>().resources_dir.clone() else { return };
+    let app = app.clone();
+    std::thread::spawn(move || {
+        if let Err(e) = import_bundled(&app, &res) {
+            eprintln!("yomitan import failed: {}", e);
+        }
+    });
+}
+
+/// Name of the profile lookups and imports go to.
+#[tauri::command]
+fn active_profile(state: tauri::State<MyState>) -> Result<String, String> {
+    Ok(state.profile.read().map_err(|e| e.to_string())?.name.clone())
+}
+
+/// Make profile `name` the active one, creating `profiles/<name>.db` on
+/// first use. Lookups, imports and the bundled dictionaries then go to its
+/// database, and it is reopened at the next launch. Waits out a running import.
+#[tauri::command(async)]
+fn switch_profile(name: String, app: AppHandle, state: tauri::State<'_, MyState>) -> Result<(), String> {
+    let db_path = profile_db_path(&app_dir(&app), &name)?;
+    if state.profile.read().map_err(|e| e.to_string())?.db_path == db_path {
+        return Ok(());
+    }
+    let (new_conn, new_reader) = open_profile(&db_path, state.resources_dir.as_deref()).map_err(|e| e.to_string())?;
+    {
+        let mut conn = state.db.lock().map_err(|e| e.to_string())?;
+        // Uses noted so far are of the profile being left, whose ids the next one reuses
+        if let Err(e) = state.usage.flush(&conn) {
+            eprintln!("recording dictionary use failed: {}", e);
+        }
+        state.usage.reset();
+        *state.reader.lock().map_err(|e| e.to_string())? = new_reader;
+        *conn = new_conn;
+        state.reload_term_index(&conn)?;
+        *state.profile.write().map_err(|e| e.to_string())? = Profile { name: name.clone(), db_path };
+    }
+    if let Err(e) = std::fs::write(app_dir(&app).join(PROFILE_FILE), &name) {
+        eprintln!("failed to remember profile {}: {}", name, e);
+    }
+    spawn_import_bundled(&app);
+    Ok(())
+}
+
+/// `cjdic --import <path>... [--db <file>]`: import into `--db` (default
+/// `yomitan.db` in the working directory) without starting Tauri, printing
+/// each summary as a JSON line. A path holding an `index.json` is one
+/// dictionary; any other directory is taken as bundled zips, recorded as the
+/// app records them so a database baked this way isn't re-imported on launch.
+pub fn import_headless(args: &[String]) -> Result<(), String> {
+    let mut db_path = PathBuf::from("yomitan.db");
+    let mut paths = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--import" => {}
+            "--db" => db_path = args.next().ok_or("--db needs a path")?.into(),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    if paths.is_empty() {
+        return Err("usage: cjdic --import <dictionary or dir of zips>... [--db <file>]".into());
+    }
+
+    let conn = open_db(&db_path, &DbOptions::default()).map_err(|e| e.to_string())?;
+    let cancel = CancellationToken::default();
+    let mut summaries = vec![];
+    for path in &paths {
+        if path.is_dir() && !path.join("index.json").exists() {
+            summaries.extend(import_bundled_zips(&conn, path, &cancel, |_| {}).map_err(|e| e.to_string())?);
+        } else {
+            summaries.push(import_one(&conn, path, false, OnConflict::ReplaceOlder, DEFAULT_BATCH_ROWS, true, &cancel, |_| {}).map_err(|e| e.to_string())?);
+        }
+    }
+    // A baked database is copied around, so it is worth compacting
+    if summaries.iter().any(|s| matches!(s.action, ImportAction::Imported | ImportAction::Replaced)) {
+        finish_import(&conn, true).map_err(|e| e.to_string())?;
+    }
+    for summary in &summaries {
+        println!("{}", serde_json::to_string(summary).map_err(|e| e.to_string())?);
+    }
+    Ok(())
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -104,40 +750,56 @@ pub fn run() {
         .plugin(tauri_plugin_clipboard_manager::init())
         .plugin(tauri_plugin_opener::init())
         .setup(|app| {
-            // On first run, import bundled dictionaries into app-local `yomitan.db`
-            if let Some(res) = find_resources_dir() {
-                let cfg_dir = app.path().config_dir();
-                let app_dir = cfg_dir
-                    .unwrap_or_else(|_| std::env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from(".")))
-                    .join("cc.polv.cjdic");
-                if let Err(e) = std::fs::create_dir_all(&app_dir) {
-                    eprintln!("failed to create app dir {}: {}", app_dir.display(), e);
-                } else {
-                    let db_path = app_dir.join("yomitan.db");
-                    if !db_path.exists() {
-                        // Prefer a `yomitan` subdirectory inside the found resources dir
-                        let res_to_use = {
-                            let cand = res.join("yomitan");
-                            if cand.exists() && cand.is_dir() {
-                                cand
-                            } else {
-                                res
-                            }
-                        };
-
-                        if let Err(e) = import_bundled_zips(&db_path, &res_to_use) {
-                            eprintln!("yomitan import failed: {:#?}", e);
-                        }
-                    }
-                }
+            let app_dir = app_dir(app.handle());
+            if let Err(e) = std::fs::create_dir_all(&app_dir) {
+                eprintln!("failed to create app dir {}: {}", app_dir.display(), e);
             }
+            let mut profile = std::fs::read_to_string(app_dir.join(PROFILE_FILE))
+                .map(|name| name.trim().to_string())
+                .unwrap_or_else(|_| DEFAULT_PROFILE.to_string());
+            let db_path = profile_db_path(&app_dir, &profile).unwrap_or_else(|e| {
+                eprintln!("{}; using the default profile", e);
+                profile = DEFAULT_PROFILE.to_string();
+                app_dir.join("yomitan.db")
+            });
 
+            let resources_dir = find_resources_dir().map(|res| {
+                // Prefer a `yomitan` subdirectory inside the found resources dir
+                let cand = res.join("yomitan");
+                if cand.exists() && cand.is_dir() {
+                    cand
+                } else {
+                    res
+                }
+            });
+            // Shared through `MyState` until `switch_profile` replaces them
+            let (conn, reader) = open_profile(&db_path, resources_dir.as_deref())?;
             app.manage(MyState {
-                lindera: LinderaAnalyzer::new()?,
+                term_index: RwLock::new(TermIndex::load(&conn)?),
+                deinflector: Deinflector::new()?,
+                db: Mutex::new(conn),
+                reader: Mutex::new(reader),
+                import_cancel: CancellationToken::default(),
+                usage: UsageTracker::default(),
+                profile: RwLock::new(Profile { name: profile, db_path }),
+                resources_dir,
+            });
+
+            let app_handle = app.handle().clone();
+            std::thread::spawn(move || loop {
+                std::thread::sleep(USAGE_FLUSH_INTERVAL);
+                flush_usage(&app_handle);
             });
+
+            spawn_import_bundled(app.handle());
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![greet, segment, search_terms])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .invoke_handler(tauri::generate_handler![greet, segment, search_terms, lookup_term, lookup_grouped, lookup_terms, lookup_by_reading, readings_for, lookup_romaji, lookup_by_sequence, lookup_by_tag, terms_sharing_glossary, search_glossary, search_prefix, count_prefix, search_prefix_brief, get_glossary, get_term_glossary, suggest, get_kanji, lookup_kanji_by_reading, lookup_kanji_by_strokes, import_dictionary, import_from_url, cancel_import, add_custom_term, remove_custom_term, list_dictionaries, dictionary_detail, is_installed, is_title_installed, check_updates, get_dictionary_styles, get_media, db_stats, check_integrity, remove_orphans, compact_database, reset_database, set_journal_mode, set_dictionary_title, set_dictionary_enabled, set_dictionary_score_offset, reorder_dictionaries, export_dictionary, export_jsonl, merge_dictionaries, uninstall_dictionary, list_pending_imports, discard_pending_import, active_profile, switch_profile])
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                flush_usage(app);
+            }
+        });
 }
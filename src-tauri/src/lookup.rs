@@ -0,0 +1,707 @@
+use std::collections::{HashMap, HashSet};
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::deinflect::Deinflector;
+use crate::kana::{fold_latin, normalize, to_hiragana, to_katakana};
+use crate::tags::{TagInfo, TagResolver};
+use crate::term_meta::{self, FreqInfo, Ipa, Pitch};
+use crate::yomitan_import::INLINE_FTS_OFFSET;
+
+#[derive(Clone, Serialize)]
+pub struct TermEntry {
+    pub id: i64,
+    pub term: String,
+    pub reading: String,
+    pub def_tags: String,
+    pub rules: String,
+    pub term_tags: String,
+    pub def_tag_info: Vec<TagInfo>,
+    pub term_tag_info: Vec<TagInfo>,
+    pub score: i64,
+    pub sequence: Option<i64>,
+    pub dict_id: i64,
+    pub dict_title: String,
+    pub glossary: Value,
+    /// `text`, `structured` or `image`: how `glossary` renders.
+    pub content_type: String,
+    pub frequencies: Vec<FreqInfo>,
+    pub pitches: Vec<Pitch>,
+    pub ipa: Vec<Ipa>,
+    /// Yomitan reason labels undone to reach `term`, e.g. `["past"]` for 食べた;
+    /// empty for a direct hit.
+    pub inflection_reasons: Vec<String>,
+}
+
+/// Columns selected by every term query; keep in sync with `TermEntry::from_row`.
+const TERM_SELECT: &str = r#"
+      SELECT
+        t.id,
+        t.term,
+        t.reading,
+        COALESCE(dt.tags,  '')  AS def_tags,
+        COALESCE(r.rules,  '')  AS rules,
+        COALESCE(tt.tags,  '')  AS term_tags,
+        t.score,
+        t.sequence,
+        COALESCE(d.display_title, d.title) AS dict_title,
+        COALESCE(g.content, t.glossary) AS glossary_json,
+        t.dict_id,
+        COALESCE(g.content_type, t.glossary_content_type, 'text') AS content_type
+      FROM terms t
+      LEFT JOIN glossaries    g  ON g.id  = t.glossary_id
+      JOIN  dictionaries  d  ON d.id  = t.dict_id AND d.enabled = 1
+      LEFT JOIN def_tag_sets  dt ON dt.id = t.def_tags_id
+      LEFT JOIN rule_sets      r ON r.id  = t.rules_id
+      LEFT JOIN term_tag_sets tt ON tt.id = t.term_tags_id
+"#;
+
+impl TermEntry {
+    fn from_row(r: &Row) -> rusqlite::Result<Self> {
+        let glossary_json: String = r.get(9)?;
+        Ok(TermEntry {
+            id: r.get(0)?,
+            term: r.get(1)?,
+            reading: r.get(2)?,
+            def_tags: r.get(3)?,
+            rules: r.get(4)?,
+            term_tags: r.get(5)?,
+            def_tag_info: vec![],
+            term_tag_info: vec![],
+            score: r.get(6)?,
+            sequence: r.get(7)?,
+            dict_id: r.get(10)?,
+            dict_title: r.get(8)?,
+            glossary: serde_json::from_str(&glossary_json).unwrap_or(Value::String(glossary_json)),
+            content_type: r.get(11)?,
+            frequencies: vec![],
+            pitches: vec![],
+            ipa: vec![],
+            inflection_reasons: vec![],
+        })
+    }
+}
+
+/// Fill in the resolved tags and `term_meta` data of each entry.
+fn attach_meta(conn: &Connection, entries: &mut [TermEntry]) -> rusqlite::Result<()> {
+    let mut tags = TagResolver::default();
+    for e in entries {
+        e.def_tag_info = tags.resolve(conn, e.dict_id, &e.def_tags)?;
+        e.term_tag_info = tags.resolve(conn, e.dict_id, &e.term_tags)?;
+        e.frequencies = term_meta::frequencies(conn, &e.term, &e.reading)?;
+        e.pitches = term_meta::pitches(conn, &e.term, &e.reading)?;
+        e.ipa = term_meta::ipa(conn, &e.term, &e.reading)?;
+    }
+    Ok(())
+}
+
+/// Entries a lookup returns when the caller gives no limit.
+pub const DEFAULT_LIMIT: usize = 100;
+
+/// At most `limit` results of a lookup, and whether there were more.
+#[derive(Serialize)]
+pub struct Limited<T> {
+    pub entries: Vec<T>,
+    pub truncated: bool,
+}
+
+/// `lookup_exact` without tags and `term_meta`, for callers that filter first.
+fn exact_rows(conn: &Connection, term: &str) -> rusqlite::Result<Vec<TermEntry>> {
+    let sql = format!("{TERM_SELECT} WHERE t.term = ?1 OR t.term_norm = ?1 ORDER BY d.sort_order, t.score + d.score_offset DESC");
+    let mut stmt = conn.prepare_cached(&sql)?;
+    stmt.query_map(params![fold_latin(term)], TermEntry::from_row)?.collect()
+}
+
+/// Exact headword match, ordered by dictionary priority then score. Width
+/// and case of Latin letters and digits don't matter: `ＰＣ` finds `pc`.
+pub fn lookup_exact(conn: &Connection, term: &str) -> rusqlite::Result<Vec<TermEntry>> {
+    let mut out = exact_rows(conn, term)?;
+    attach_meta(conn, &mut out)?;
+    Ok(out)
+}
+
+/// Entries whose headword or reading is `surface`, as matched by
+/// segmentation, folded as `lookup_exact` folds.
+pub fn lookup_surface(conn: &Connection, surface: &str) -> rusqlite::Result<Vec<TermEntry>> {
+    let sql = format!("{TERM_SELECT} WHERE t.term = ?1 OR t.term_norm = ?1 OR t.reading = ?1 ORDER BY d.sort_order, t.score + d.score_offset DESC");
+    let mut stmt = conn.prepare_cached(&sql)?;
+    let mut out = stmt.query_map(params![fold_latin(surface)], TermEntry::from_row)?.collect::<rusqlite::Result<Vec<_>>>()?;
+    attach_meta(conn, &mut out)?;
+    Ok(out)
+}
+
+/// Surfaces bound per `lookup_surfaces` query, well under SQLite's variable limit.
+const SURFACE_CHUNK: usize = 500;
+
+/// `lookup_surface` for many surfaces at once, keyed by the surfaces as given.
+/// Every surface gets a key, with no entries if nothing matched.
+pub fn lookup_surfaces(conn: &Connection, surfaces: &[String]) -> rusqlite::Result<HashMap<String, Vec<TermEntry>>> {
+    let mut out: HashMap<String, Vec<TermEntry>> = surfaces.iter().map(|s| (s.clone(), vec![])).collect();
+    // Inputs that normalize alike share their matches
+    let mut by_normalized: HashMap<String, Vec<&String>> = HashMap::new();
+    for s in surfaces {
+        by_normalized.entry(fold_latin(&normalize(s)).into_owned()).or_default().push(s);
+    }
+    let keys: Vec<&String> = by_normalized.keys().collect();
+    for chunk in keys.chunks(SURFACE_CHUNK) {
+        let placeholders = (1..=chunk.len()).map(|i| format!("?{i}")).collect::<Vec<_>>().join(",");
+        let sql = format!(
+            "{TERM_SELECT} WHERE t.term IN ({placeholders}) OR t.term_norm IN ({placeholders}) OR t.reading IN ({placeholders})
+            ORDER BY d.sort_order, t.score + d.score_offset DESC"
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let mut entries = stmt
+            .query_map(rusqlite::params_from_iter(chunk), TermEntry::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        attach_meta(conn, &mut entries)?;
+        for e in entries {
+            let mut matched = vec![fold_latin(&e.term)];
+            if e.reading != matched[0] {
+                matched.push(e.reading.as_str().into());
+            }
+            for surface in matched.iter().filter_map(|k| by_normalized.get(k.as_ref())).flatten() {
+                if let Some(list) = out.get_mut(*surface) {
+                    list.push(e.clone());
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Entries read as `reading`, in either kana script. Kana-only headwords have
+/// no separate reading, so they match on the headword.
+pub fn lookup_by_reading(conn: &Connection, reading: &str) -> rusqlite::Result<Vec<TermEntry>> {
+    let sql = format!(
+        "{TERM_SELECT} WHERE t.reading IN (?1, ?2) OR (t.reading = '' AND t.term IN (?1, ?2))
+        ORDER BY d.sort_order, t.score + d.score_offset DESC"
+    );
+    let reading = normalize(reading);
+    let mut stmt = conn.prepare_cached(&sql)?;
+    let mut out = stmt
+        .query_map(params![to_hiragana(&reading), to_katakana(&reading)], TermEntry::from_row)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    attach_meta(conn, &mut out)?;
+    Ok(out)
+}
+
+/// The distinct readings enabled dictionaries give headword `term`, those
+/// more entries share first and ties in dictionary priority order. Kana
+/// entries, stored without a reading, add none.
+pub fn readings_for(conn: &Connection, term: &str) -> rusqlite::Result<Vec<String>> {
+    conn.prepare_cached(
+        "SELECT t.reading
+         FROM terms t JOIN dictionaries d ON d.id = t.dict_id AND d.enabled = 1
+         WHERE (t.term = ?1 OR t.term_norm = ?1) AND t.reading != ''
+         GROUP BY t.reading
+         ORDER BY COUNT(*) DESC, MIN(d.sort_order), MIN(t.id)",
+    )?
+    .query_map(params![fold_latin(&normalize(term))], |r| r.get(0))?
+    .collect()
+}
+
+/// Every entry dictionary `dict_id` groups under `sequence`, i.e. the senses
+/// and spellings of one headword, in bank order.
+pub fn lookup_by_sequence(conn: &Connection, dict_id: i64, sequence: i64) -> rusqlite::Result<Vec<TermEntry>> {
+    let sql = format!("{TERM_SELECT} WHERE t.dict_id = ?1 AND t.sequence = ?2 ORDER BY t.id");
+    let mut stmt = conn.prepare_cached(&sql)?;
+    let mut out = stmt.query_map(params![dict_id, sequence], TermEntry::from_row)?.collect::<rusqlite::Result<Vec<_>>>()?;
+    attach_meta(conn, &mut out)?;
+    Ok(out)
+}
+
+/// Every entry of dictionary `dict_id` carrying `tag` as a definition or term tag.
+pub fn lookup_by_tag(conn: &Connection, dict_id: i64, tag: &str) -> rusqlite::Result<Vec<TermEntry>> {
+    let sql = format!(
+        "{TERM_SELECT} WHERE t.dict_id = ?1 AND t.id IN (SELECT term_id FROM term_tag_link WHERE tag_name = ?2)
+        ORDER BY t.score + d.score_offset DESC, t.id"
+    );
+    let mut stmt = conn.prepare_cached(&sql)?;
+    let mut out = stmt.query_map(params![dict_id, tag], TermEntry::from_row)?.collect::<rusqlite::Result<Vec<_>>>()?;
+    attach_meta(conn, &mut out)?;
+    Ok(out)
+}
+
+/// Upper bound on `terms_sharing_glossary` hits; a stock gloss like a
+/// name dictionary's "surname" can be shared by thousands of entries.
+const SHARED_GLOSSARY_LIMIT: i64 = 200;
+
+/// Entries of other headwords with a glossary identical to one of `term`'s,
+/// which is matched as `lookup_exact` matches it. Glossaries are interned,
+/// so sharing one means sharing its row, and such headwords are often
+/// synonyms or variant spellings. Glossaries kept on their term rows, from
+/// dictionaries imported without interning, have no row to share and are
+/// left out on both sides.
+pub fn terms_sharing_glossary(conn: &Connection, term: &str) -> rusqlite::Result<Vec<TermEntry>> {
+    // `term_norm` is NULL where folding leaves the headword as is, so this is
+    // every headword's folded form
+    let sql = format!(
+        "{TERM_SELECT}
+        WHERE t.glossary_id IN (
+            SELECT q.glossary_id FROM terms q JOIN dictionaries qd ON qd.id = q.dict_id AND qd.enabled = 1
+            WHERE (q.term = ?1 OR q.term_norm = ?1) AND q.glossary_id IS NOT NULL
+        ) AND COALESCE(t.term_norm, t.term) <> ?1
+        ORDER BY d.sort_order, t.score + d.score_offset DESC, t.id
+        LIMIT ?2"
+    );
+    let mut stmt = conn.prepare_cached(&sql)?;
+    let mut out = stmt
+        .query_map(params![fold_latin(&normalize(term)), SHARED_GLOSSARY_LIMIT], TermEntry::from_row)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    attach_meta(conn, &mut out)?;
+    Ok(out)
+}
+
+/// Exact matches for `word` and for every base form it deinflects to whose
+/// word class agrees with the applied rules. Direct hits come first. Stops
+/// at `limit` entries; only those get their tags and `term_meta` looked up.
+pub fn lookup_term(conn: &Connection, deinflector: &Deinflector, word: &str, limit: usize) -> rusqlite::Result<Limited<TermEntry>> {
+    let mut seen = HashSet::new();
+    let mut out = Vec::new();
+    let mut truncated = false;
+    'candidates: for candidate in deinflector.deinflect(&normalize(word)) {
+        for mut entry in exact_rows(conn, &candidate.term)? {
+            if candidate.accepts(&entry.rules) && seen.insert(entry.id) {
+                if out.len() == limit {
+                    truncated = true;
+                    break 'candidates;
+                }
+                entry.inflection_reasons = candidate.reasons.clone();
+                out.push(entry);
+            }
+        }
+    }
+    attach_meta(conn, &mut out)?;
+    Ok(Limited { entries: out, truncated })
+}
+
+/// One sense of a `GroupedEntry`: a single `terms` row's definitions.
+#[derive(Serialize)]
+pub struct Sense {
+    pub id: i64,
+    pub glossary: Value,
+    pub content_type: String,
+    pub def_tags: String,
+    pub def_tag_info: Vec<TagInfo>,
+    pub rules: String,
+    pub score: i64,
+    pub sequence: Option<i64>,
+}
+
+/// The rows one dictionary has for a headword and reading, as Yomitan shows
+/// them: the shared fields once and each row as a numbered sense.
+#[derive(Serialize)]
+pub struct GroupedEntry {
+    pub term: String,
+    pub reading: String,
+    pub dict_id: i64,
+    pub dict_title: String,
+    /// Union of the senses' term tags, in first-seen order.
+    pub term_tags: String,
+    pub term_tag_info: Vec<TagInfo>,
+    pub frequencies: Vec<FreqInfo>,
+    pub pitches: Vec<Pitch>,
+    pub ipa: Vec<Ipa>,
+    pub inflection_reasons: Vec<String>,
+    /// In the dictionary's own order, which `terms.id` keeps: banks are
+    /// imported in order and their rows inserted as they appear.
+    pub senses: Vec<Sense>,
+}
+
+/// `lookup_term` with rows grouped by (dictionary, term, reading). Groups
+/// keep the order of their first row.
+pub fn lookup_grouped(conn: &Connection, deinflector: &Deinflector, word: &str) -> rusqlite::Result<Vec<GroupedEntry>> {
+    let mut groups: Vec<GroupedEntry> = Vec::new();
+    let mut index: HashMap<(i64, String, String), usize> = HashMap::new();
+    for e in lookup_term(conn, deinflector, word, usize::MAX)?.entries {
+        let key = (e.dict_id, e.term.clone(), e.reading.clone());
+        let i = *index.entry(key).or_insert_with(|| {
+            groups.push(GroupedEntry {
+                term: e.term.clone(),
+                reading: e.reading.clone(),
+                dict_id: e.dict_id,
+                dict_title: e.dict_title.clone(),
+                term_tags: String::new(),
+                term_tag_info: vec![],
+                frequencies: e.frequencies.clone(),
+                pitches: e.pitches.clone(),
+                ipa: e.ipa.clone(),
+                inflection_reasons: e.inflection_reasons.clone(),
+                senses: vec![],
+            });
+            groups.len() - 1
+        });
+        let group = &mut groups[i];
+        for tag in e.term_tag_info {
+            if !group.term_tag_info.iter().any(|t| t.name == tag.name) {
+                group.term_tag_info.push(tag);
+            }
+        }
+        for name in e.term_tags.split_whitespace() {
+            if !group.term_tags.split_whitespace().any(|t| t == name) {
+                if !group.term_tags.is_empty() {
+                    group.term_tags.push(' ');
+                }
+                group.term_tags.push_str(name);
+            }
+        }
+        group.senses.push(Sense {
+            id: e.id,
+            glossary: e.glossary,
+            content_type: e.content_type,
+            def_tags: e.def_tags,
+            def_tag_info: e.def_tag_info,
+            rules: e.rules,
+            score: e.score,
+            sequence: e.sequence,
+        });
+    }
+    for g in &mut groups {
+        g.senses.sort_by_key(|s| s.id);
+        // Frequencies a sequenced dictionary keyed on another headword of the same group
+        let mut sequences: Vec<i64> = g.senses.iter().filter_map(|s| s.sequence).collect();
+        sequences.sort_unstable();
+        sequences.dedup();
+        for sequence in sequences {
+            for f in term_meta::sequence_frequencies(conn, g.dict_id, sequence)? {
+                if !g.frequencies.iter().any(|o| o.dict_title == f.dict_title && o.value == f.value) {
+                    g.frequencies.push(f);
+                }
+            }
+        }
+    }
+    Ok(groups)
+}
+
+/// Escape GLOB metacharacters; with `wildcards`, `*` and `?` keep their meaning.
+fn glob_escape(s: &str, wildcards: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '*' | '?' if wildcards => out.push(c),
+            '*' | '?' | '[' => {
+                out.push('[');
+                out.push(c);
+                out.push(']');
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// What the prefix searches and `count_prefix` match, `?1` bound to `prefix_pattern`.
+const PREFIX_WHERE: &str = "(t.term GLOB ?1 OR t.term_norm GLOB ?1)";
+
+fn prefix_pattern(prefix: &str, wildcards: bool) -> String {
+    format!("{}*", glob_escape(&fold_latin(&normalize(prefix)), wildcards))
+}
+
+/// Headwords starting with `prefix`, one entry per (term, reading), highest
+/// score first, from the `offset`-th (term, reading) on. With `wildcards`,
+/// `?` in `prefix` matches any one character and `*` any run of characters.
+pub fn search_prefix(conn: &Connection, prefix: &str, limit: usize, offset: usize, wildcards: bool) -> rusqlite::Result<Limited<TermEntry>> {
+    // `t.id` last so pages split ties the same way every time
+    let sql = format!("{TERM_SELECT} WHERE {PREFIX_WHERE} ORDER BY t.score + d.score_offset DESC, d.sort_order, t.id");
+    let mut stmt = conn.prepare_cached(&sql)?;
+    let mut seen = HashSet::new();
+    let mut out = Vec::new();
+    let mut truncated = false;
+    for entry in stmt.query_map(params![prefix_pattern(prefix, wildcards)], TermEntry::from_row)? {
+        let entry = entry?;
+        if seen.insert((entry.term.clone(), entry.reading.clone())) {
+            if seen.len() <= offset {
+                continue;
+            }
+            if out.len() == limit {
+                truncated = true;
+                break;
+            }
+            out.push(entry);
+        }
+    }
+    attach_meta(conn, &mut out)?;
+    Ok(Limited { entries: out, truncated })
+}
+
+/// How many (term, reading) pairs `search_prefix` pages through for `prefix`.
+pub fn count_prefix(conn: &Connection, prefix: &str, wildcards: bool) -> rusqlite::Result<i64> {
+    let sql = format!(
+        "SELECT COUNT(*) FROM (
+            SELECT DISTINCT t.term, t.reading FROM terms t JOIN dictionaries d ON d.id = t.dict_id AND d.enabled = 1
+            WHERE {PREFIX_WHERE}
+        )"
+    );
+    conn.prepare_cached(&sql)?.query_row(params![prefix_pattern(prefix, wildcards)], |r| r.get(0))
+}
+
+/// Edit distance between `a` and `b`, counted in chars.
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        cur[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            cur[j + 1] = (prev[j] + usize::from(ca != cb)).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
+/// Exact matches for `query`, or if there are none, headwords and readings
+/// within `max_distance` edits of it, nearest then highest score with its
+/// dictionary's `score_offset` first, one entry per (term, reading). Candidates must share the query's first
+/// character and be within `max_distance` of its length, so a typo in the
+/// first character finds nothing. Latin text is folded as `lookup_exact`
+/// folds it before edits are counted.
+pub fn suggest(conn: &Connection, query: &str, max_distance: usize, limit: usize) -> rusqlite::Result<Vec<TermEntry>> {
+    let query = normalize(query);
+    let mut exact = lookup_exact(conn, &query)?;
+    let chars: Vec<char> = fold_latin(&query).chars().collect();
+    if !exact.is_empty() || chars.is_empty() {
+        exact.truncate(limit);
+        return Ok(exact);
+    }
+
+    let sql = format!(
+        "{TERM_SELECT} WHERE (t.term GLOB ?1 AND length(t.term) BETWEEN ?2 AND ?3)
+            OR (t.term_norm GLOB ?1 AND length(t.term_norm) BETWEEN ?2 AND ?3)
+            OR (t.reading GLOB ?1 AND length(t.reading) BETWEEN ?2 AND ?3)
+        ORDER BY t.score + d.score_offset DESC, d.sort_order"
+    );
+    let pattern = format!("{}*", glob_escape(&chars[0].to_string(), false));
+    let (min_len, max_len) = (chars.len().saturating_sub(max_distance), chars.len() + max_distance);
+    let mut stmt = conn.prepare_cached(&sql)?;
+    let mut seen = HashSet::new();
+    let mut scored = Vec::new();
+    for entry in stmt.query_map(params![pattern, min_len as i64, max_len as i64], TermEntry::from_row)? {
+        let entry = entry?;
+        let distance = [&entry.term, &entry.reading]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .map(|s| levenshtein(&chars, &fold_latin(s).chars().collect::<Vec<_>>()))
+            .min()
+            .unwrap_or(usize::MAX);
+        if distance <= max_distance && seen.insert((entry.term.clone(), entry.reading.clone())) {
+            scored.push((distance, entry));
+        }
+    }
+    // Stable, so equal distances keep the query's score then dictionary order
+    scored.sort_by_key(|(distance, _)| *distance);
+    let mut out: Vec<TermEntry> = scored.into_iter().take(limit).map(|(_, e)| e).collect();
+    attach_meta(conn, &mut out)?;
+    Ok(out)
+}
+
+/// A term without its definitions, for result lists that load glossaries
+/// with `get_glossary` only as they are shown.
+#[derive(Serialize)]
+pub struct TermBrief {
+    pub id: i64,
+    pub term: String,
+    pub reading: String,
+    pub score: i64,
+    pub dict_id: i64,
+    pub dict_title: String,
+    /// `None` for a glossary stored on the term row; `term_glossary` loads those.
+    pub glossary_hash: Option<String>,
+    /// How the glossary renders, as `TermEntry::content_type`.
+    pub content_type: String,
+}
+
+/// `search_prefix` returning `TermBrief`s, paged the same way.
+pub fn search_prefix_brief(conn: &Connection, prefix: &str, limit: usize, offset: usize, wildcards: bool) -> rusqlite::Result<Vec<TermBrief>> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT t.id, t.term, t.reading, t.score, t.dict_id, COALESCE(d.display_title, d.title), g.hash,
+                COALESCE(g.content_type, t.glossary_content_type, 'text')
+         FROM terms t
+         LEFT JOIN glossaries g ON g.id = t.glossary_id
+         JOIN dictionaries d ON d.id = t.dict_id AND d.enabled = 1
+         WHERE {PREFIX_WHERE}
+         ORDER BY t.score + d.score_offset DESC, d.sort_order, t.id"
+    ))?;
+    let rows = stmt.query_map(params![prefix_pattern(prefix, wildcards)], |r| {
+        Ok(TermBrief {
+            id: r.get(0)?,
+            term: r.get(1)?,
+            reading: r.get(2)?,
+            score: r.get(3)?,
+            dict_id: r.get(4)?,
+            dict_title: r.get(5)?,
+            glossary_hash: r.get(6)?,
+            content_type: r.get(7)?,
+        })
+    })?;
+    let mut seen = HashSet::new();
+    let mut out = Vec::new();
+    for brief in rows {
+        let brief = brief?;
+        if seen.insert((brief.term.clone(), brief.reading.clone())) {
+            if seen.len() <= offset {
+                continue;
+            }
+            if out.len() == limit {
+                break;
+            }
+            out.push(brief);
+        }
+    }
+    Ok(out)
+}
+
+/// The interned glossary with content hash `hash`, or `None` if there is none.
+pub fn get_glossary(conn: &Connection, hash: &str) -> rusqlite::Result<Option<Value>> {
+    let content: Option<String> = conn
+        .prepare_cached("SELECT content FROM glossaries WHERE hash = ?1")?
+        .query_row(params![hash], |r| r.get(0))
+        .optional()?;
+    Ok(content.map(|c| serde_json::from_str(&c).unwrap_or(Value::String(c))))
+}
+
+/// The glossary of term `id`, interned or not, or `None` if there is no such term.
+pub fn term_glossary(conn: &Connection, id: i64) -> rusqlite::Result<Option<Value>> {
+    let content: Option<String> = conn
+        .prepare_cached("SELECT COALESCE(g.content, t.glossary) FROM terms t LEFT JOIN glossaries g ON g.id = t.glossary_id WHERE t.id = ?1")?
+        .query_row(params![id], |r| r.get(0))
+        .optional()?;
+    Ok(content.map(|c| serde_json::from_str(&c).unwrap_or(Value::String(c))))
+}
+
+/// Upper bound on `search_glossary` hits; common words match most of a dictionary.
+const GLOSSARY_SEARCH_LIMIT: i64 = 200;
+
+/// Terms whose glossary text contains `query` as a phrase, best FTS rank first.
+pub fn search_glossary(conn: &Connection, query: &str) -> rusqlite::Result<Vec<TermEntry>> {
+    let phrase = format!("\"{}\"", query.replace('"', "\"\""));
+    // See `INLINE_FTS_OFFSET` for the second half of `hits`. The `IN` has the
+    // planner look terms up by id rather than scan them for hits.
+    let sql = format!(
+        "WITH f AS (SELECT rowid, rank FROM glossary_fts WHERE glossary_fts MATCH ?1 ORDER BY rank LIMIT ?2),
+        hits AS (
+            SELECT q.id, f.rank FROM f CROSS JOIN terms q ON q.glossary_id = f.rowid
+            UNION ALL
+            SELECT f.rowid + {INLINE_FTS_OFFSET}, f.rank FROM f WHERE f.rowid < 0
+        )
+        {TERM_SELECT}
+        JOIN hits h ON h.id = t.id
+        WHERE t.id IN (SELECT id FROM hits)
+        ORDER BY h.rank, d.sort_order, t.score + d.score_offset DESC"
+    );
+    let mut stmt = conn.prepare_cached(&sql)?;
+    let mut out = stmt
+        .query_map(params![phrase, GLOSSARY_SEARCH_LIMIT], TermEntry::from_row)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    attach_meta(conn, &mut out)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yomitan_import::{create_schema, import_one, CancellationToken, OnConflict, DEFAULT_BATCH_ROWS};
+
+    /// Import dictionary `title` holding term bank `bank`, interning its
+    /// glossaries only with `intern`.
+    fn import_into(conn: &Connection, title: &str, bank: &str, intern: bool) {
+        let dir = tempfile::tempdir().unwrap();
+        let index = serde_json::json!({ "title": title, "revision": "1", "format": 3 });
+        std::fs::write(dir.path().join("index.json"), index.to_string()).unwrap();
+        std::fs::write(dir.path().join("term_bank_1.json"), bank).unwrap();
+        import_one(conn, dir.path(), false, OnConflict::ReplaceOlder, DEFAULT_BATCH_ROWS, intern, &CancellationToken::default(), |_| {}).unwrap();
+    }
+
+    fn test_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        create_schema(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn sharing_glossary_folds_the_headword() {
+        let conn = test_db();
+        import_into(
+            &conn,
+            "Interned",
+            r#"[["ＰＣ", "ぴーしー", "", "", 0, ["personal computer"], 1, ""], ["パソコン", "", "", "", 0, ["personal computer"], 2, ""], ["計算機", "けいさんき", "", "", 0, ["calculator"], 3, ""]]"#,
+            true,
+        );
+        import_into(&conn, "Inline", r#"[["パーソナルコンピューター", "", "", "", 0, ["personal computer"], 1, ""]]"#, false);
+
+        for query in ["pc", "PC", "ＰＣ"] {
+            let shared: Vec<_> = terms_sharing_glossary(&conn, query).unwrap().into_iter().map(|e| e.term).collect();
+            // The inline copy of the glossary is no interned row, so it isn't shared
+            assert_eq!(shared, ["パソコン"], "{query}");
+        }
+        let shared: Vec<_> = terms_sharing_glossary(&conn, "パソコン").unwrap().into_iter().map(|e| e.term).collect();
+        assert_eq!(shared, ["ＰＣ"]);
+        assert!(terms_sharing_glossary(&conn, "パーソナルコンピューター").unwrap().is_empty());
+    }
+
+    #[test]
+    fn brief_prefix_pages_match_full_ones() {
+        let conn = test_db();
+        // Equal scores, so only the id tie-break orders them
+        import_into(
+            &conn,
+            "Prefix",
+            r#"[["食べる", "たべる", "", "", 0, ["to eat"], 1, ""], ["食う", "くう", "", "", 0, ["to eat"], 2, ""], ["食事", "しょくじ", "", "", 0, ["meal"], 3, ""], ["食べる", "たべる", "", "", 0, ["to live on"], 4, ""], ["食品", "しょくひん", "", "", 0, ["food"], 5, ""]]"#,
+            true,
+        );
+        let mut paged = vec![];
+        for offset in [0, 2, 4] {
+            let briefs: Vec<_> = search_prefix_brief(&conn, "食", 2, offset, false).unwrap().into_iter().map(|b| (b.id, b.term)).collect();
+            let full: Vec<_> = search_prefix(&conn, "食", 2, offset, false).unwrap().entries.into_iter().map(|e| (e.id, e.term)).collect();
+            assert_eq!(briefs, full, "{offset}");
+            paged.extend(briefs.into_iter().map(|(_, term)| term));
+        }
+        assert_eq!(paged, ["食べる", "食う", "食事", "食品"]);
+        assert!(search_prefix_brief(&conn, "食", 2, 4, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn suggest_folds_latin_typos() {
+        let conn = test_db();
+        import_into(&conn, "Latin", r#"[["PC", "ぴーしー", "", "", 0, ["personal computer"], 1, ""]]"#, true);
+        for query in ["pcs", "PCS", "ｐｃｓ"] {
+            let found: Vec<_> = suggest(&conn, query, 1, 10).unwrap().into_iter().map(|e| e.term).collect();
+            assert_eq!(found, ["PC"], "{query}");
+        }
+    }
+
+    #[test]
+    fn levenshtein_counts_char_edits() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert_eq!(levenshtein(&chars("kitten"), &chars("sitting")), 3);
+        assert_eq!(levenshtein(&chars("食べる"), &chars("食べた")), 1);
+        assert_eq!(levenshtein(&chars(""), &chars("たべる")), 3);
+        assert_eq!(levenshtein(&chars("たべる"), &chars("たべる")), 0);
+    }
+
+    #[test]
+    fn suggest_ranks_by_distance_then_effective_score() {
+        let conn = test_db();
+        import_into(
+            &conn,
+            "Main",
+            r#"[["食べる", "たべる", "", "", 1, ["to eat"], 1, ""], ["食べた", "", "", "", 5, ["ate"], 2, ""], ["食パン", "しょくぱん", "", "", 9, ["bread"], 3, ""], ["飲べる", "", "", "", 9, ["typo"], 4, ""], ["食べさせる", "たべさせる", "", "", 9, ["to feed"], 5, ""]]"#,
+            true,
+        );
+        import_into(&conn, "Boosted", r#"[["食べれ", "", "", "", 0, ["eat!"], 1, ""]]"#, true);
+        conn.execute("UPDATE dictionaries SET score_offset = 10 WHERE title = 'Boosted'", []).unwrap();
+        let terms = |query: &str, max_distance: usize| -> Vec<String> {
+            suggest(&conn, query, max_distance, 10).unwrap().into_iter().map(|e| e.term).collect()
+        };
+
+        // An exact hit is all there is, though 食べた is an edit away
+        assert_eq!(terms("食べる", 1), ["食べる"]);
+        // 飲べる is an edit away too but starts differently, and 食べさせる
+        // is too long to be a candidate
+        assert_eq!(terms("食べろ", 1), ["食べれ", "食べた", "食べる"]);
+        // 食パン scores highest but is two edits away
+        assert_eq!(terms("食べろ", 2), ["食べれ", "食べた", "食べる", "食パン"]);
+        assert!(terms("飯", 1).is_empty());
+    }
+}
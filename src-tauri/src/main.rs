@@ -2,5 +2,14 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // Handled before Tauri starts, so a build step can bake the database without a window
+    if args.first().map(String::as_str) == Some("--import") {
+        if let Err(e) = cjdic_lib::import_headless(&args) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
     cjdic_lib::run()
 }
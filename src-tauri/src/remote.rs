@@ -0,0 +1,83 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use serde::Serialize;
+use serde_json::Value;
+use tempfile::NamedTempFile;
+use zip::ZipArchive;
+
+use crate::dictionaries::Updatable;
+use crate::yomitan_import::{compare_revisions, ImportError};
+
+/// Download `url` into a temporary file, written as it arrives rather than
+/// held in memory, and check that it is a zip archive. The file is deleted
+/// when the returned handle drops.
+pub async fn download_zip(url: &str) -> Result<NamedTempFile, ImportError> {
+    let failed = |reason: String| ImportError::Download { url: url.to_string(), reason };
+    let mut response = reqwest::get(url)
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| failed(e.to_string()))?;
+    let mut file = tempfile::Builder::new()
+        .prefix("cjdic-")
+        .suffix(".zip")
+        .tempfile()
+        .map_err(|e| failed(e.to_string()))?;
+    while let Some(chunk) = response.chunk().await.map_err(|e| failed(e.to_string()))? {
+        file.write_all(&chunk).map_err(|e| failed(e.to_string()))?;
+    }
+    file.flush().map_err(|e| failed(e.to_string()))?;
+    let reopened = file.reopen().map_err(|e| failed(e.to_string()))?;
+    ZipArchive::new(reopened).map_err(|source| ImportError::NotAZip { path: PathBuf::from(url), source })?;
+    Ok(file)
+}
+
+#[derive(Serialize)]
+pub struct UpdateInfo {
+    pub id: i64,
+    pub title: String,
+    pub revision: String,
+    /// The revision in the remote index.json; `None` when `error` is set.
+    pub latest_revision: Option<String>,
+    /// Whether `latest_revision` is newer than `revision`.
+    pub update_available: bool,
+    /// The remote index.json's `downloadUrl`, else the installed one's.
+    pub download_url: Option<String>,
+    /// Why the remote index.json couldn't be fetched or read.
+    pub error: Option<String>,
+}
+
+/// Fetch `dict`'s remote index.json and compare its revision with the
+/// installed one. Nothing is downloaded beyond the index.
+pub async fn check_update(dict: Updatable) -> UpdateInfo {
+    let latest = fetch_index(&dict.index_url).await;
+    let (latest_revision, download_url, error) = match latest {
+        Ok(index) => (
+            index.get("revision").and_then(Value::as_str).map(str::trim).filter(|s| !s.is_empty()).map(str::to_string),
+            index.get("downloadUrl").and_then(Value::as_str).map(str::to_string).or(dict.download_url),
+            None,
+        ),
+        Err(e) => (None, dict.download_url, Some(e)),
+    };
+    let error = error.or_else(|| latest_revision.is_none().then(|| "index.json has no revision".to_string()));
+    UpdateInfo {
+        update_available: latest_revision.as_deref().is_some_and(|rev| compare_revisions(rev, &dict.revision).is_gt()),
+        id: dict.id,
+        title: dict.title,
+        revision: dict.revision,
+        latest_revision,
+        download_url,
+        error,
+    }
+}
+
+async fn fetch_index(url: &str) -> Result<Value, String> {
+    let text = reqwest::get(url)
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.to_string())?
+        .text()
+        .await
+        .map_err(|e| e.to_string())?;
+    serde_json::from_str(text.strip_prefix('\u{FEFF}').unwrap_or(&text)).map_err(|e| format!("index.json is not valid JSON: {}", e))
+}
@@ -0,0 +1,195 @@
+/// The selector `scope_css` is given for dictionary `dict_id`. The frontend
+/// puts the same attribute on the element wrapping that dictionary's entries.
+pub fn dictionary_scope(dict_id: i64) -> String {
+    format!("[data-dictionary-id=\"{}\"]", dict_id)
+}
+
+/// Drop `/* ... */` comments, leaving strings alone.
+fn strip_comments(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut quote = None;
+    let mut chars = css.chars().peekable();
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(q) => {
+                out.push(c);
+                if c == '\\' {
+                    out.extend(chars.next());
+                } else if c == q {
+                    quote = None;
+                }
+            }
+            None if c == '/' && chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = '\0';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            None => {
+                if c == '"' || c == '\'' {
+                    quote = Some(c);
+                }
+                out.push(c);
+            }
+        }
+    }
+    out
+}
+
+/// Byte index of the first of `stops` in `s` that is outside strings and
+/// outside any (), [] or {} opened in `s`.
+fn find_top_level(s: &str, stops: &[u8]) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        match quote {
+            Some(q) if b == b'\\' || b == q => {
+                if b == q {
+                    quote = None;
+                } else {
+                    i += 1;
+                }
+            }
+            Some(_) => {}
+            None => match b {
+                b'"' | b'\'' => quote = Some(b),
+                b'\\' => i += 1,
+                _ if depth == 0 && stops.contains(&b) => return Some(i),
+                b'(' | b'[' | b'{' => depth += 1,
+                b')' | b']' | b'}' => depth = depth.saturating_sub(1),
+                _ => {}
+            },
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Prefix each selector in the comma-separated list `selectors` with `scope`.
+fn scope_selectors(selectors: &str, scope: &str, out: &mut String) {
+    let mut rest = selectors;
+    let mut first = true;
+    loop {
+        let end = find_top_level(rest, b",").unwrap_or(rest.len());
+        let selector = rest[..end].trim();
+        if !selector.is_empty() {
+            if !first {
+                out.push_str(", ");
+            }
+            out.push_str(scope);
+            out.push(' ');
+            out.push_str(selector);
+            first = false;
+        }
+        if end == rest.len() {
+            break;
+        }
+        rest = &rest[end + 1..];
+    }
+}
+
+/// The name of the at-rule `prelude` starts, without its `@`, if it is one.
+fn at_rule_name(prelude: &str) -> Option<&str> {
+    let at_rule = prelude.strip_prefix('@')?;
+    at_rule.split(|c: char| !c.is_alphanumeric() && c != '-').next()
+}
+
+fn scope_rules(css: &str, scope: &str, out: &mut String) {
+    let mut rest = css.trim_start();
+    // A rule cut off before its `{` ends the sheet, as it does for browsers
+    while let Some(i) = find_top_level(rest, b"{;") {
+        let prelude = rest[..i].trim();
+        if rest.as_bytes()[i] == b';' {
+            // `@import`, `@charset` and `@namespace` go; they don't apply in an injected sheet.
+            // Other statements, like `@layer a, b;` ordering layers, stay as written
+            let dropped = at_rule_name(prelude).is_some_and(|name| ["import", "charset", "namespace"].iter().any(|n| name.eq_ignore_ascii_case(n)));
+            if !dropped {
+                out.push_str(prelude);
+                out.push_str(";\n");
+            }
+            rest = rest[i + 1..].trim_start();
+            continue;
+        }
+        let body = &rest[i + 1..];
+        let body_end = find_top_level(body, b"}").unwrap_or(body.len());
+        let (body, after) = (&body[..body_end], body.get(body_end + 1..).unwrap_or(""));
+
+        match at_rule_name(prelude) {
+            Some(name) => {
+                out.push_str(prelude);
+                out.push_str(" {");
+                if ["media", "supports", "container", "layer"].iter().any(|n| name.eq_ignore_ascii_case(n)) {
+                    out.push('\n');
+                    scope_rules(body, scope, out);
+                } else {
+                    // `@font-face`, `@keyframes` and the like hold no selectors to scope
+                    out.push_str(body);
+                }
+                out.push_str("}\n");
+            }
+            None => {
+                scope_selectors(prelude, scope, out);
+                out.push_str(" {");
+                out.push_str(body);
+                out.push_str("}\n");
+            }
+        }
+        rest = after.trim_start();
+    }
+}
+
+/// Prefix every selector of `css` with `scope`, descending into `@media`,
+/// `@supports`, `@container` and `@layer` blocks.
+pub fn scope_css(css: &str, scope: &str) -> String {
+    let mut out = String::with_capacity(css.len() + css.len() / 4);
+    scope_rules(&strip_comments(css), scope, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCOPE: &str = "[data-dictionary-id=\"1\"]";
+
+    #[test]
+    fn scopes_each_selector() {
+        assert_eq!(scope_css(".a, span > .b { color: red }", SCOPE), format!("{SCOPE} .a, {SCOPE} span > .b {{ color: red }}\n"));
+    }
+
+    #[test]
+    fn comments_go_but_strings_keep_them() {
+        assert_eq!(scope_css("/* { */ .a { content: \"/* x */\" }", SCOPE), format!("{SCOPE} .a {{ content: \"/* x */\" }}\n"));
+    }
+
+    #[test]
+    fn quoted_braces_and_commas_are_no_syntax() {
+        assert_eq!(
+            scope_css(".a[title=\"x, {y}\"], .b { content: '}' } .c { }", SCOPE),
+            format!("{SCOPE} .a[title=\"x, {{y}}\"], {SCOPE} .b {{ content: '}}' }}\n{SCOPE} .c {{ }}\n")
+        );
+    }
+
+    #[test]
+    fn descends_into_media_but_not_font_face() {
+        assert_eq!(
+            scope_css("@media (max-width: 600px) { @supports (display: grid) { .a { b: c } } } @font-face { font-family: X; src: url(x.woff) }", SCOPE),
+            format!("@media (max-width: 600px) {{\n@supports (display: grid) {{\n{SCOPE} .a {{ b: c }}\n}}\n}}\n@font-face {{ font-family: X; src: url(x.woff) }}\n")
+        );
+    }
+
+    #[test]
+    fn drops_only_statements_that_cannot_apply() {
+        assert_eq!(
+            scope_css("@charset \"utf-8\"; @import url(x.css); @NAMESPACE svg url(y); @layer base, gloss; @layer base { .a { b: c } }", SCOPE),
+            format!("@layer base, gloss;\n@layer base {{\n{SCOPE} .a {{ b: c }}\n}}\n")
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+
+#[derive(Clone, Serialize)]
+pub struct TagInfo {
+    pub name: String,
+    pub category: Option<String>,
+    pub notes: Option<String>,
+    pub sort_order: i64,
+}
+
+/// Expands space-separated tag names into their `tags` rows, loading each
+/// dictionary's tag bank once per resolver.
+#[derive(Default)]
+pub struct TagResolver {
+    by_dict: HashMap<i64, HashMap<String, TagInfo>>,
+}
+
+impl TagResolver {
+    fn dict_tags(&mut self, conn: &Connection, dict_id: i64) -> rusqlite::Result<&HashMap<String, TagInfo>> {
+        match self.by_dict.entry(dict_id) {
+            Entry::Occupied(e) => Ok(e.into_mut()),
+            Entry::Vacant(e) => {
+                let mut stmt = conn.prepare_cached("SELECT name, category, notes, sort_order FROM tags WHERE dict_id = ?1")?;
+                let tags = stmt
+                    .query_map(params![dict_id], |r| {
+                        Ok(TagInfo {
+                            name: r.get(0)?,
+                            category: r.get(1)?,
+                            notes: r.get(2)?,
+                            sort_order: r.get(3)?,
+                        })
+                    })?
+                    .map(|t| t.map(|t| (t.name.clone(), t)))
+                    .collect::<rusqlite::Result<_>>()?;
+                Ok(e.insert(tags))
+            }
+        }
+    }
+
+    /// Tags named in `names` as defined by dictionary `dict_id`. Names its tag
+    /// bank doesn't define are kept with no metadata.
+    pub fn resolve(&mut self, conn: &Connection, dict_id: i64, names: &str) -> rusqlite::Result<Vec<TagInfo>> {
+        let known = self.dict_tags(conn, dict_id)?;
+        Ok(names
+            .split_whitespace()
+            .map(|name| {
+                known.get(name).cloned().unwrap_or_else(|| TagInfo {
+                    name: name.to_string(),
+                    category: None,
+                    notes: None,
+                    sort_order: 0,
+                })
+            })
+            .collect())
+    }
+}
+
+/// Fill `term_tag_link` with one row per tag name in each term's definition
+/// and term tag sets, for dictionary `dict_id` or, given `None`, every term.
+/// Existing links are left alone, so this can be re-run.
+pub fn link_term_tags(conn: &Connection, dict_id: Option<i64>) -> rusqlite::Result<()> {
+    let mut select = conn.prepare(
+        "SELECT t.id, COALESCE(dt.tags, ''), COALESCE(tt.tags, '')
+         FROM terms t
+         LEFT JOIN def_tag_sets  dt ON dt.id = t.def_tags_id
+         LEFT JOIN term_tag_sets tt ON tt.id = t.term_tags_id
+         WHERE (t.def_tags_id IS NOT NULL OR t.term_tags_id IS NOT NULL)
+           AND (?1 IS NULL OR t.dict_id = ?1)",
+    )?;
+    let mut insert = conn.prepare("INSERT OR IGNORE INTO term_tag_link (term_id, tag_name) VALUES (?1, ?2)")?;
+    let mut rows = select.query(params![dict_id])?;
+    while let Some(r) = rows.next()? {
+        let term_id: i64 = r.get(0)?;
+        let (def_tags, term_tags): (String, String) = (r.get(1)?, r.get(2)?);
+        for name in def_tags.split_whitespace().chain(term_tags.split_whitespace()) {
+            insert.execute(params![term_id, name])?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use serde_json::Value;
+
+/// One frequency dictionary's ranking of a term. Lower `value` is more common
+/// for rank-based lists, but the scale is up to each dictionary.
+#[derive(Clone, Serialize)]
+pub struct FreqInfo {
+    pub dict_title: String,
+    pub value: i64,
+    pub display: Option<String>,
+}
+
+/// `term_meta` rows of `mode` for `term` from enabled dictionaries whose
+/// reading is unset or matches `reading`, as (dictionary title, data) pairs.
+fn meta_rows(conn: &Connection, mode: &str, term: &str, reading: &str) -> rusqlite::Result<Vec<(String, Value)>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT COALESCE(d.display_title, d.title), m.data
+         FROM term_meta m
+         JOIN dictionaries d ON d.id = m.dict_id AND d.enabled = 1
+         WHERE m.term = ?1 AND m.mode = ?2
+           AND (m.reading IS NULL OR m.reading = ?3 OR (?3 = '' AND m.reading = ?1))
+         ORDER BY d.sort_order, m.id",
+    )?;
+    let rows = stmt.query_map(params![term, mode, reading], |r| {
+        let data: String = r.get(1)?;
+        Ok((r.get(0)?, serde_json::from_str(&data).unwrap_or(Value::Null)))
+    })?;
+    rows.collect()
+}
+
+/// Frequency data of a `freq` row: a number, a string, `{value, displayValue}`,
+/// or any of those wrapped as `{reading, frequency}`.
+pub fn parse_freq(data: &Value) -> Option<(i64, Option<String>)> {
+    match data {
+        Value::Number(n) => Some((n.as_i64().or_else(|| n.as_f64().map(|f| f as i64))?, None)),
+        Value::String(s) => Some((leading_number(s)?, Some(s.clone()))),
+        Value::Object(o) => {
+            if let Some(inner) = o.get("frequency") {
+                return parse_freq(inner);
+            }
+            let (value, display) = parse_freq(o.get("value")?)?;
+            let display = o.get("displayValue").and_then(Value::as_str).map(str::to_string).or(display);
+            Some((value, display))
+        }
+        _ => None,
+    }
+}
+
+/// Digit group separators display values write counts with, as in `1,234`.
+const GROUP_SEPARATORS: &[char] = &[',', '，', '\'', '_', '\u{00A0}', '\u{2009}', '\u{202F}'];
+
+/// The number a display string such as `"1,234"` or `"12 (㋕)"` starts with.
+/// `None` when a separator isn't followed by a group of three digits, as in
+/// `"1,5"`, which can't be read as a count without guessing.
+fn leading_number(s: &str) -> Option<i64> {
+    let mut digits = String::new();
+    let mut group = None;
+    for c in s.trim_start().chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            group = group.map(|n| n + 1);
+        } else if GROUP_SEPARATORS.contains(&c) && !digits.is_empty() && group.is_none_or(|n| n == 3) {
+            group = Some(0);
+        } else {
+            break;
+        }
+    }
+    if group.is_some_and(|n| n != 3) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// The reading a `term_meta` row of `mode` is limited to, or `None` if it
+/// applies to every reading of its term. A `freq` row is limited only when
+/// wrapped as `{reading, frequency}`; `{value, displayValue}` is not, and a
+/// blank reading limits nothing.
+pub fn meta_reading<'a>(mode: &str, data: &'a Value) -> Option<&'a str> {
+    let scoped = mode != "freq" || data.get("frequency").is_some();
+    data.get("reading").and_then(Value::as_str).filter(|r| scoped && !r.trim().is_empty())
+}
+
+pub fn frequencies(conn: &Connection, term: &str, reading: &str) -> rusqlite::Result<Vec<FreqInfo>> {
+    Ok(meta_rows(conn, "freq", term, reading)?
+        .into_iter()
+        .filter_map(|(dict_title, data)| {
+            let (value, display) = parse_freq(&data)?;
+            Some(FreqInfo { dict_title, value, display })
+        })
+        .collect())
+}
+
+/// Frequencies dictionary `dict_id` gives sequence group `sequence` as a
+/// whole, whichever headword or reading of the group each row was keyed on.
+pub fn sequence_frequencies(conn: &Connection, dict_id: i64, sequence: i64) -> rusqlite::Result<Vec<FreqInfo>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT COALESCE(d.display_title, d.title), m.data
+         FROM term_meta m
+         JOIN dictionaries d ON d.id = m.dict_id AND d.enabled = 1
+         WHERE m.dict_id = ?1 AND m.sequence = ?2 AND m.mode = 'freq'
+         ORDER BY m.id",
+    )?;
+    let rows = stmt.query_map(params![dict_id, sequence], |r| {
+        let data: String = r.get(1)?;
+        Ok((r.get::<_, String>(0)?, serde_json::from_str(&data).unwrap_or(Value::Null)))
+    })?;
+    let mut out = Vec::new();
+    for row in rows {
+        let (dict_title, data) = row?;
+        if let Some((value, display)) = parse_freq(&data) {
+            out.push(FreqInfo { dict_title, value, display });
+        }
+    }
+    Ok(out)
+}
+
+/// Set `term_meta.sequence` on the rows of sequenced dictionary `dict_id` to
+/// the sequence of the dictionary's own term each row describes: same
+/// headword, and the same reading unless the row has none. Rows matching no
+/// sequenced term are left NULL.
+pub fn link_sequences(conn: &Connection, dict_id: i64) -> rusqlite::Result<()> {
+    // Best-scored term first, so a reading-less row takes the main entry's group
+    let mut by_term: HashMap<String, Vec<(String, i64)>> = HashMap::new();
+    let mut select = conn.prepare(
+        "SELECT term, reading, sequence FROM terms
+         WHERE dict_id = ?1 AND sequence IS NOT NULL
+         ORDER BY score DESC, id",
+    )?;
+    let mut rows = select.query(params![dict_id])?;
+    while let Some(r) = rows.next()? {
+        by_term.entry(r.get(0)?).or_default().push((r.get(1)?, r.get(2)?));
+    }
+    if by_term.is_empty() {
+        return Ok(());
+    }
+
+    let mut select = conn.prepare("SELECT id, term, reading FROM term_meta WHERE dict_id = ?1")?;
+    let mut update = conn.prepare("UPDATE term_meta SET sequence = ?2 WHERE id = ?1")?;
+    let mut rows = select.query(params![dict_id])?;
+    while let Some(r) = rows.next()? {
+        let id: i64 = r.get(0)?;
+        let (term, reading): (String, Option<String>) = (r.get(1)?, r.get(2)?);
+        let Some(candidates) = by_term.get(&term) else { continue };
+        // A term with no reading is its own reading, as in `meta_rows`
+        let found = candidates.iter().find(|(r, _)| match reading.as_deref() {
+            None => true,
+            Some(reading) => r == reading || (r.is_empty() && reading == term),
+        });
+        if let Some((_, sequence)) = found {
+            update.execute(params![id, sequence])?;
+        }
+    }
+    Ok(())
+}
+
+/// One pitch accent pattern: the mora after which pitch drops (0 for heiban),
+/// plus the positions of nasalized and devoiced morae.
+#[derive(Clone, Serialize)]
+pub struct Pitch {
+    pub position: i64,
+    pub nasal: Vec<i64>,
+    pub devoice: Vec<i64>,
+}
+
+/// A pitch position given as a number, a number in a string, or an H/L
+/// pattern per mora such as `"LHHL"`.
+fn parse_position(v: &Value) -> Option<i64> {
+    match v {
+        Value::Number(n) => n.as_i64(),
+        Value::String(s) => s.trim().parse().ok().or_else(|| {
+            let pattern: Vec<char> = s.trim().chars().map(|c| c.to_ascii_uppercase()).collect();
+            if pattern.is_empty() || pattern.iter().any(|&c| c != 'H' && c != 'L') {
+                return None;
+            }
+            let drop = pattern.windows(2).position(|w| w == ['H', 'L']).map_or(0, |i| i + 1);
+            Some(drop as i64)
+        }),
+        _ => None,
+    }
+}
+
+/// `nasal` and `devoice` are either a single mora index or a list of them.
+fn parse_morae(v: Option<&Value>) -> Vec<i64> {
+    match v {
+        Some(Value::Number(n)) => n.as_i64().into_iter().collect(),
+        Some(Value::Array(a)) => a.iter().filter_map(Value::as_i64).collect(),
+        _ => vec![],
+    }
+}
+
+fn parse_pitch(v: &Value) -> Option<Pitch> {
+    match v {
+        Value::Object(o) => Some(Pitch {
+            position: parse_position(o.get("position")?)?,
+            nasal: parse_morae(o.get("nasal")),
+            devoice: parse_morae(o.get("devoice")),
+        }),
+        bare => Some(Pitch {
+            position: parse_position(bare)?,
+            nasal: vec![],
+            devoice: vec![],
+        }),
+    }
+}
+
+/// Pitch data of a `pitch` row: `{reading, pitches: [...]}`, where older
+/// dictionaries may give `pitches` as a single entry or entries as bare positions.
+pub fn pitches(conn: &Connection, term: &str, reading: &str) -> rusqlite::Result<Vec<Pitch>> {
+    let mut out = Vec::new();
+    for (_, data) in meta_rows(conn, "pitch", term, reading)? {
+        match data.get("pitches") {
+            Some(Value::Array(items)) => out.extend(items.iter().filter_map(parse_pitch)),
+            Some(one) => out.extend(parse_pitch(one)),
+            None => {}
+        }
+    }
+    Ok(out)
+}
+
+/// One IPA transcription of a reading, with the tags a dictionary qualifies
+/// it by, such as a dialect.
+#[derive(Clone, Serialize)]
+pub struct Ipa {
+    pub ipa: String,
+    pub tags: Vec<String>,
+}
+
+/// Tags as an array of names or one space-separated string.
+fn parse_tag_names(v: Option<&Value>) -> Vec<String> {
+    match v {
+        Some(Value::Array(a)) => a.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+        Some(Value::String(s)) => s.split_whitespace().map(str::to_string).collect(),
+        _ => vec![],
+    }
+}
+
+fn parse_transcription(v: &Value) -> Option<Ipa> {
+    let (ipa, tags) = match v {
+        Value::Object(o) => (o.get("ipa")?.as_str()?, parse_tag_names(o.get("tags"))),
+        bare => (bare.as_str()?, vec![]),
+    };
+    let ipa = ipa.trim();
+    (!ipa.is_empty()).then(|| Ipa { ipa: ipa.to_string(), tags })
+}
+
+/// IPA data of an `ipa` row: `{reading, transcriptions: [{ipa, tags}]}`, where
+/// `transcriptions` may also be a single entry and entries bare strings.
+pub fn ipa(conn: &Connection, term: &str, reading: &str) -> rusqlite::Result<Vec<Ipa>> {
+    let mut out = Vec::new();
+    for (_, data) in meta_rows(conn, "ipa", term, reading)? {
+        match data.get("transcriptions") {
+            Some(Value::Array(items)) => out.extend(items.iter().filter_map(parse_transcription)),
+            Some(one) => out.extend(parse_transcription(one)),
+            None => {}
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn display_strings_with_digit_groups() {
+        assert_eq!(parse_freq(&json!("1,234")), Some((1234, Some("1,234".to_string()))));
+        assert_eq!(parse_freq(&json!("12,345,678")).map(|f| f.0), Some(12_345_678));
+        assert_eq!(parse_freq(&json!("1\u{202F}234 (㋕)")).map(|f| f.0), Some(1234));
+        assert_eq!(parse_freq(&json!({ "value": 5, "displayValue": "5㋕" })), Some((5, Some("5㋕".to_string()))));
+        assert_eq!(parse_freq(&json!("123㋕")).map(|f| f.0), Some(123));
+    }
+
+    #[test]
+    fn ambiguous_separators_give_none() {
+        assert_eq!(parse_freq(&json!("1,5")), None);
+        assert_eq!(parse_freq(&json!("1,2345")), None);
+        assert_eq!(parse_freq(&json!("1,")), None);
+        assert_eq!(parse_freq(&json!(",123")), None);
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection};
+
+/// A dictionary's use is recorded at most this often.
+pub const USAGE_THROTTLE_SECS: i64 = 60;
+
+#[derive(Default)]
+struct Usage {
+    /// Unix time each dictionary's use was last recorded.
+    recorded: HashMap<i64, i64>,
+    /// Recorded uses not yet written, by dictionary.
+    pending: HashMap<i64, i64>,
+}
+
+/// When each dictionary last answered a lookup. Lookups only note it in
+/// memory; `flush` writes `dictionaries.last_used_at` from the background.
+#[derive(Default)]
+pub struct UsageTracker(Mutex<Usage>);
+
+fn unix_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
+
+impl UsageTracker {
+    /// Note that dictionaries `dict_ids` answered a lookup just now, unless
+    /// noted within the last `USAGE_THROTTLE_SECS`.
+    pub fn record(&self, dict_ids: impl IntoIterator<Item = i64>) {
+        let now = unix_now();
+        let Ok(mut usage) = self.0.lock() else { return };
+        let usage = &mut *usage;
+        for id in dict_ids {
+            if usage.recorded.get(&id).is_none_or(|&at| now - at >= USAGE_THROTTLE_SECS) {
+                usage.recorded.insert(id, now);
+                usage.pending.insert(id, now);
+            }
+        }
+    }
+
+    /// Write the uses noted since the last flush in one transaction. On
+    /// failure they stay pending for the next one.
+    pub fn flush(&self, conn: &Connection) -> rusqlite::Result<usize> {
+        let pending = match self.0.lock() {
+            Ok(mut usage) => std::mem::take(&mut usage.pending),
+            Err(_) => return Ok(0),
+        };
+        if pending.is_empty() {
+            return Ok(0);
+        }
+        let written = (|| {
+            let tx = conn.unchecked_transaction()?;
+            {
+                let mut update = tx.prepare_cached("UPDATE dictionaries SET last_used_at = datetime(?2, 'unixepoch') WHERE id = ?1")?;
+                for (&id, &at) in &pending {
+                    update.execute(params![id, at])?;
+                }
+            }
+            tx.commit()
+        })();
+        match written {
+            Ok(()) => Ok(pending.len()),
+            Err(e) => {
+                if let Ok(mut usage) = self.0.lock() {
+                    for (id, at) in pending {
+                        usage.pending.entry(id).or_insert(at);
+                    }
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Forget everything noted, for when dictionary ids stop meaning the
+    /// same dictionaries. Call `flush` first to keep pending uses.
+    pub fn reset(&self) {
+        if let Ok(mut usage) = self.0.lock() {
+            *usage = Usage::default();
+        }
+    }
+}
@@ -1,33 +1,328 @@
-use anyhow::Context;
-use rusqlite::{params, Connection, Transaction};
+use rayon::prelude::*;
+use rusqlite::{params, Connection, OptionalExtension, Statement, Transaction};
+use serde::de::{self, Deserializer as _, SeqAccess, Visitor};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
 use serde_json::Value;
 use sha1::Sha1;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
 use std::fs::File;
-use std::io::Read;
-use std::path::{Path, PathBuf};
+use std::io::{BufRead, BufReader, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::path::{Component, Path, PathBuf};
 use zip::ZipArchive;
 
-fn sha1_hex(s: &str) -> String {
+use crate::content::{glossary_content_type, glossary_text, media_paths};
+use crate::dictionaries;
+use crate::glossary::Glossary;
+use crate::kana::{fold_latin, normalize};
+use crate::styles;
+use crate::tags;
+use crate::term_meta;
+
+/// What to do when a dictionary with the same title but another revision is installed.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnConflict {
+    /// Leave the installed one and don't import.
+    Skip,
+    /// Replace it if its revision is older, otherwise skip.
+    ReplaceOlder,
+    /// Install alongside it.
+    KeepBoth,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportAction {
+    Imported,
+    /// Imported in place of older revisions of the same title.
+    Replaced,
+    Skipped,
+    /// Cancelled part way through and rolled back.
+    Aborted,
+}
+
+#[derive(Clone, Serialize)]
+pub struct ImportSummary {
+    pub title: String,
+    pub revision: String,
+    pub action: ImportAction,
+    pub terms: usize,
+    pub term_meta: usize,
+    /// Tags new to the dictionary; a name repeated across tag banks counts once.
+    pub tags: usize,
+    pub kanji: usize,
+    /// Media files stored for glossaries to show.
+    pub media: usize,
+    /// Bank entries dropped for not having the shape their bank kind requires.
+    pub skipped: usize,
+    pub was_duplicate: bool,
+    /// Title of the installed dictionary this one duplicates, when `was_duplicate`.
+    pub duplicate_of: Option<String>,
+}
+
+impl ImportSummary {
+    /// Whether the dictionary was written, so its transaction should commit.
+    fn landed(&self) -> bool {
+        matches!(self.action, ImportAction::Imported | ImportAction::Replaced)
+    }
+
+    fn aborted(title: String, revision: String) -> Self {
+        ImportSummary {
+            action: ImportAction::Aborted,
+            ..ImportSummary::skipped(title, revision, None)
+        }
+    }
+
+    fn skipped(title: String, revision: String, duplicate_of: Option<String>) -> Self {
+        ImportSummary {
+            title,
+            revision,
+            action: ImportAction::Skipped,
+            terms: 0,
+            term_meta: 0,
+            tags: 0,
+            kanji: 0,
+            media: 0,
+            skipped: 0,
+            was_duplicate: duplicate_of.is_some(),
+            duplicate_of,
+        }
+    }
+}
+
+/// Why an import failed. Serializes as `{kind, message, path, bank}`, with
+/// `kind` the variant in snake case for the frontend to branch on.
+#[derive(Debug)]
+pub enum ImportError {
+    /// The dictionary, or the resources dir holding bundled ones, couldn't be opened.
+    OpenFailed { path: PathBuf, source: std::io::Error },
+    NotAZip { path: PathBuf, source: zip::result::ZipError },
+    /// There is no `index.json`, so it isn't a Yomitan dictionary.
+    MissingIndex { path: PathBuf },
+    /// `index.json` isn't JSON, or its title or revision is blank.
+    InvalidIndex { path: PathBuf, reason: String },
+    /// `index.json` is JSON but not a Yomitan index, e.g. another app's file.
+    NotYomitan { path: PathBuf, reason: String },
+    /// A bank file, `styles.css` or a media file couldn't be read or parsed.
+    BankParse { bank: String, source: serde_json::Error },
+    /// Fetching the dictionary from `url` failed.
+    Download { url: String, reason: String },
+    Db(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl ImportError {
+    fn bank(bank: &str, source: serde_json::Error) -> Self {
+        ImportError::BankParse { bank: bank.to_string(), source }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            ImportError::OpenFailed { .. } => "open_failed",
+            ImportError::NotAZip { .. } => "not_a_zip",
+            ImportError::MissingIndex { .. } => "missing_index",
+            ImportError::InvalidIndex { .. } => "invalid_index",
+            ImportError::NotYomitan { .. } => "not_yomitan",
+            ImportError::BankParse { .. } => "bank_parse",
+            ImportError::Download { .. } => "download",
+            ImportError::Db(_) => "db",
+        }
+    }
+
+    fn path(&self) -> Option<&Path> {
+        match self {
+            ImportError::OpenFailed { path, .. }
+            | ImportError::NotAZip { path, .. }
+            | ImportError::MissingIndex { path }
+            | ImportError::InvalidIndex { path, .. }
+            | ImportError::NotYomitan { path, .. } => Some(path),
+            ImportError::Download { url, .. } => Some(Path::new(url)),
+            ImportError::BankParse { .. } | ImportError::Db(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::OpenFailed { path, source } => write!(f, "opening {}: {}", path.display(), source),
+            ImportError::NotAZip { path, source } => write!(f, "{} is not a zip archive: {}", path.display(), source),
+            ImportError::MissingIndex { path } => write!(f, "{} is not a Yomitan dictionary (no index.json)", path.display()),
+            ImportError::InvalidIndex { path, reason } => write!(f, "{}: index.json {}", path.display(), reason),
+            ImportError::NotYomitan { path, reason } => write!(f, "{} is not a Yomitan dictionary (index.json {})", path.display(), reason),
+            ImportError::BankParse { bank, source } => write!(f, "loading {}: {}", bank, source),
+            ImportError::Download { url, reason } => write!(f, "downloading {}: {}", url, reason),
+            ImportError::Db(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<rusqlite::Error> for ImportError {
+    fn from(e: rusqlite::Error) -> Self {
+        ImportError::Db(Box::new(e))
+    }
+}
+
+impl Serialize for ImportError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("ImportError", 4)?;
+        s.serialize_field("kind", self.kind())?;
+        s.serialize_field("message", &self.to_string())?;
+        s.serialize_field("path", &self.path())?;
+        s.serialize_field("bank", &match self {
+            ImportError::BankParse { bank, .. } => Some(bank),
+            _ => None,
+        })?;
+        s.end()
+    }
+}
+
+/// Shared flag asking a running import to stop. Checked after each batch of rows.
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Order revisions naturally: digit runs compare as numbers, so `"10" > "9"`
+/// and `"2024.02.01" > "2024.1.31"`, and anything else compares as text.
+pub fn compare_revisions(a: &str, b: &str) -> std::cmp::Ordering {
+    fn runs(s: &str) -> Vec<&str> {
+        let mut out = Vec::new();
+        let mut start = 0;
+        let mut prev_digit = None;
+        for (i, c) in s.char_indices() {
+            let digit = c.is_ascii_digit();
+            if prev_digit.is_some_and(|p| p != digit) {
+                out.push(&s[start..i]);
+                start = i;
+            }
+            prev_digit = Some(digit);
+        }
+        if start < s.len() {
+            out.push(&s[start..]);
+        }
+        out
+    }
+    for (x, y) in runs(a).into_iter().zip(runs(b)) {
+        let ord = match (x.parse::<u128>(), y.parse::<u128>()) {
+            (Ok(x), Ok(y)) => x.cmp(&y),
+            _ => x.cmp(y),
+        };
+        if ord.is_ne() {
+            return ord;
+        }
+    }
+    runs(a).len().cmp(&runs(b).len())
+}
+
+/// Emitted after each bank file of a dictionary has been loaded.
+#[derive(Clone, Serialize)]
+pub struct Progress {
+    pub title: String,
+    /// Bank files loaded so far across all bank kinds.
+    pub bank: usize,
+    /// Entries processed so far across all banks.
+    pub entries: usize,
+}
+
+/// Lowercase hex SHA1 of `s`, as glossaries are interned under.
+pub fn sha1_hex(s: &str) -> String {
     // Use the `digest` helper on `Sha1::from` which returns a byte buffer
-    let digest = Sha1::from(s).digest();
-    let bytes = digest.bytes();
+    digest_hex(&Sha1::from(s))
+}
+
+fn digest_hex(hasher: &Sha1) -> String {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    let bytes = hasher.digest().bytes();
     let mut out = String::with_capacity(bytes.len() * 2);
-    for b in &bytes {
-        out.push_str(&format!("{:02x}", b));
+    for b in bytes {
+        out.push(HEX[(b >> 4) as usize] as char);
+        out.push(HEX[(b & 0xf) as usize] as char);
     }
     out
 }
 
-fn create_schema(conn: &Connection) -> rusqlite::Result<()> {
-    conn.execute_batch(
-        "PRAGMA journal_mode = WAL;
-        PRAGMA foreign_keys = ON;
-        PRAGMA synchronous  = NORMAL;
-        PRAGMA cache_size   = -65536;
-        PRAGMA temp_store   = MEMORY;
+/// Passes reads through while feeding every byte to `hasher`.
+struct HashingReader<'a, R> {
+    inner: R,
+    hasher: &'a mut Sha1,
+}
+
+impl<R: Read> Read for HashingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
 
-        CREATE TABLE IF NOT EXISTS schema_meta (
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Feeds each element of the top-level JSON array of the bank file `bank` to
+/// `f` as it is parsed, so a bank is never held in memory as a whole.
+fn for_each_entry<R: Read>(bank: &str, reader: R, mut f: impl FnMut(Value) -> Result<(), ImportError>) -> Result<(), ImportError> {
+    struct EntryVisitor<'a, F> {
+        f: &'a mut F,
+        err: &'a mut Option<ImportError>,
+    }
+
+    impl<'de, F: FnMut(Value) -> Result<(), ImportError>> Visitor<'de> for EntryVisitor<'_, F> {
+        type Value = ();
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a JSON array of bank entries")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+            while let Some(e) = seq.next_element::<Value>()? {
+                if let Err(err) = (self.f)(e) {
+                    *self.err = Some(err);
+                    return Err(de::Error::custom("entry handler failed"));
+                }
+            }
+            Ok(())
+        }
+    }
+
+    let mut err = None;
+    let mut reader = BufReader::new(reader);
+    // Some editors save banks with a UTF-8 BOM, which JSON parsers reject
+    let has_bom = reader.fill_buf().map_err(|e| ImportError::bank(bank, serde_json::Error::io(e)))?.starts_with(UTF8_BOM);
+    if has_bom {
+        reader.consume(UTF8_BOM.len());
+    }
+    let mut de = serde_json::Deserializer::from_reader(reader);
+    let res = de.deserialize_seq(EntryVisitor { f: &mut f, err: &mut err });
+    if let Some(e) = err {
+        return Err(e);
+    }
+    res.and_then(|()| de.end()).map_err(|e| ImportError::bank(bank, e))
+}
+
+pub fn create_schema(conn: &Connection) -> rusqlite::Result<()> {
+    // Tables made below are already in their latest form, so a new database
+    // starts at the latest version instead of running every migration
+    let fresh = !conn.prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'dictionaries'")?.exists([])?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_meta (
             key   TEXT PRIMARY KEY,
             value TEXT NOT NULL
         );
@@ -40,13 +335,27 @@ fn create_schema(conn: &Connection) -> rusqlite::Result<()> {
             description  TEXT,
             is_bundled   INTEGER NOT NULL DEFAULT 1,
             sort_order   INTEGER NOT NULL DEFAULT 0,
+            enabled      INTEGER NOT NULL DEFAULT 1,
+            format_version INTEGER,
+            content_hash TEXT,
+            is_custom    INTEGER NOT NULL DEFAULT 0,
+            score_offset INTEGER NOT NULL DEFAULT 0,
+            sequenced    INTEGER NOT NULL DEFAULT 0,
+            frequency_mode TEXT NOT NULL DEFAULT 'rank-based',
+            styles       TEXT,
+            display_title TEXT,
+            is_updatable INTEGER NOT NULL DEFAULT 0,
+            index_url    TEXT,
+            download_url TEXT,
+            last_used_at TEXT,
             installed_at TEXT    NOT NULL DEFAULT (datetime('now')),
             UNIQUE (title, revision)
         );
         CREATE TABLE IF NOT EXISTS glossaries (
             id      INTEGER PRIMARY KEY,
             hash    TEXT NOT NULL UNIQUE,
-            content TEXT NOT NULL
+            content TEXT NOT NULL,
+            content_type TEXT NOT NULL DEFAULT 'text'
         );
         CREATE TABLE IF NOT EXISTS def_tag_sets (id INTEGER PRIMARY KEY, tags TEXT NOT NULL UNIQUE);
         CREATE TABLE IF NOT EXISTS term_tag_sets (id INTEGER PRIMARY KEY, tags TEXT NOT NULL UNIQUE);
@@ -55,11 +364,14 @@ fn create_schema(conn: &Connection) -> rusqlite::Result<()> {
             id INTEGER PRIMARY KEY,
             dict_id INTEGER NOT NULL REFERENCES dictionaries(id) ON DELETE CASCADE,
             term TEXT NOT NULL,
+            term_norm TEXT,
             reading TEXT NOT NULL,
             def_tags_id INTEGER,
             rules_id INTEGER,
             score INTEGER NOT NULL DEFAULT 0,
-            glossary_id INTEGER NOT NULL REFERENCES glossaries(id),
+            glossary_id INTEGER REFERENCES glossaries(id),
+            glossary TEXT,
+            glossary_content_type TEXT,
             sequence INTEGER,
             term_tags_id INTEGER
         );
@@ -69,7 +381,8 @@ fn create_schema(conn: &Connection) -> rusqlite::Result<()> {
             term TEXT NOT NULL,
             mode TEXT NOT NULL,
             reading TEXT,
-            data TEXT NOT NULL
+            data TEXT NOT NULL,
+            sequence INTEGER
         );
         CREATE TABLE IF NOT EXISTS tags (
             id INTEGER PRIMARY KEY,
@@ -91,248 +404,1876 @@ fn create_schema(conn: &Connection) -> rusqlite::Result<()> {
             meanings TEXT NOT NULL DEFAULT '[]',
             stats TEXT NOT NULL DEFAULT '{}'
         );
+        CREATE TABLE IF NOT EXISTS term_tag_link (
+            term_id  INTEGER NOT NULL REFERENCES terms(id) ON DELETE CASCADE,
+            tag_name TEXT    NOT NULL,
+            PRIMARY KEY (term_id, tag_name)
+        ) WITHOUT ROWID;
+        CREATE VIRTUAL TABLE IF NOT EXISTS glossary_fts USING fts5(
+            text,
+            content = '',
+            contentless_delete = 1
+        );
+        CREATE TABLE IF NOT EXISTS kanji_meta (
+            id INTEGER PRIMARY KEY,
+            dict_id INTEGER NOT NULL REFERENCES dictionaries(id) ON DELETE CASCADE,
+            character TEXT NOT NULL,
+            mode TEXT NOT NULL,
+            data TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS media (
+            dict_id INTEGER NOT NULL REFERENCES dictionaries(id) ON DELETE CASCADE,
+            path    TEXT    NOT NULL,
+            data    BLOB,
+            PRIMARY KEY (dict_id, path)
+        );
+        CREATE TABLE IF NOT EXISTS import_progress (
+            dict_id   INTEGER PRIMARY KEY REFERENCES dictionaries(id) ON DELETE CASCADE,
+            banks     INTEGER NOT NULL DEFAULT 0,
+            entries   INTEGER NOT NULL DEFAULT 0,
+            terms     INTEGER NOT NULL DEFAULT 0,
+            term_meta INTEGER NOT NULL DEFAULT 0,
+            tags      INTEGER NOT NULL DEFAULT 0,
+            kanji     INTEGER NOT NULL DEFAULT 0,
+            skipped   INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE TABLE IF NOT EXISTS bundled_imports (
+            file_name TEXT PRIMARY KEY,
+            size      INTEGER NOT NULL,
+            modified  INTEGER NOT NULL
+        );
         ",
     )?;
 
     let mut stmt = conn.prepare("SELECT 1 FROM schema_meta WHERE key = 'schema_version'")?;
-    if stmt.exists([])? == false {
-        conn.prepare("INSERT INTO schema_meta (key, value) VALUES ('schema_version', '1')")?.execute([])?;
+    if !stmt.exists([])? {
+        // Databases from before `schema_meta` count as version 1
+        let version = if fresh { SCHEMA_VERSION } else { 1 };
+        conn.execute("INSERT INTO schema_meta (key, value) VALUES ('schema_version', ?1)", params![version.to_string()])?;
         conn.prepare("INSERT INTO schema_meta (key, value) VALUES ('created_at', datetime('now'))")?.execute([])?;
     }
+    if fresh {
+        return Ok(());
+    }
+
+    migrate(conn)
+}
+
+/// The version `MIGRATIONS` bring a database up to.
+const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64 + 1;
+
+/// A schema upgrade step. Steps must be idempotent: `create_schema` creates
+/// tables an old database lacks in their latest form before migrating it,
+/// and a step may be re-run if the process dies before its version bump commits.
+type Migration = fn(&Transaction) -> rusqlite::Result<()>;
+
+/// `MIGRATIONS[i]` upgrades schema version `i + 1` to `i + 2`.
+const MIGRATIONS: &[Migration] = &[
+    // v1 -> v2: index glossaries imported before `glossary_fts` existed
+    rebuild_glossary_fts,
+    // v2 -> v3: per-dictionary on/off switch
+    |tx| add_column(tx, "dictionaries", "enabled", "INTEGER NOT NULL DEFAULT 1"),
+    // v3 -> v4: index.json `format` (or legacy `version`)
+    |tx| add_column(tx, "dictionaries", "format_version", "INTEGER"),
+    // v4 -> v5: bank content hash for duplicate detection
+    |tx| add_column(tx, "dictionaries", "content_hash", "TEXT"),
+    // v5 -> v6: re-index with link text kept
+    rebuild_glossary_fts,
+    // v6 -> v7: the dictionary holding user-added terms
+    |tx| add_column(tx, "dictionaries", "is_custom", "INTEGER NOT NULL DEFAULT 0"),
+    // v7 -> v8: NFC headwords and readings imported before normalization
+    normalize_terms,
+    // v8 -> v9: per-dictionary boost to term scores in lookups
+    |tx| add_column(tx, "dictionaries", "score_offset", "INTEGER NOT NULL DEFAULT 0"),
+    // v9 -> v10: index.json `sequenced` and `frequencyMode`
+    |tx| {
+        add_column(tx, "dictionaries", "sequenced", "INTEGER NOT NULL DEFAULT 0")?;
+        add_column(tx, "dictionaries", "frequency_mode", "TEXT NOT NULL DEFAULT 'rank-based'")
+    },
+    // v10 -> v11: split interned tag sets into `term_tag_link` (`create_schema` made the table)
+    |tx| tags::link_term_tags(tx, None),
+    // v11 -> v12: sequence group of each `term_meta` row of a sequenced dictionary
+    |tx| {
+        add_column(tx, "term_meta", "sequence", "INTEGER")?;
+        let ids = tx
+            .prepare("SELECT id FROM dictionaries WHERE sequenced = 1")?
+            .query_map([], |r| r.get(0))?
+            .collect::<rusqlite::Result<Vec<i64>>>()?;
+        for id in ids {
+            term_meta::link_sequences(tx, id)?;
+        }
+        Ok(())
+    },
+    // v12 -> v13: the dictionary's `styles.css`, scoped to it
+    |tx| add_column(tx, "dictionaries", "styles", "TEXT"),
+    // v13 -> v14: glossaries stored on the term row rather than interned
+    inline_glossary_column,
+    // v14 -> v15: a title to show in place of index.json's
+    |tx| add_column(tx, "dictionaries", "display_title", "TEXT"),
+    // v15 -> v16: how each glossary renders
+    tag_content_types,
+    // v16 -> v17: index.json `isUpdatable`, `indexUrl` and `downloadUrl`
+    |tx| {
+        add_column(tx, "dictionaries", "is_updatable", "INTEGER NOT NULL DEFAULT 0")?;
+        add_column(tx, "dictionaries", "index_url", "TEXT")?;
+        add_column(tx, "dictionaries", "download_url", "TEXT")
+    },
+    // v17 -> v18: when a lookup last returned the dictionary's entries
+    |tx| add_column(tx, "dictionaries", "last_used_at", "TEXT"),
+    // v18 -> v19: headwords with width and case folded, for matching
+    fold_terms,
+    // v19 -> v20: rule sets interned as sorted token sets
+    normalize_rule_sets,
+    // v20 -> v21: re-index with image alt text kept
+    rebuild_glossary_fts,
+];
+
+/// Rewrite `rule_sets` rows interned as written into `rule_set` form. A row
+/// that then equals another is folded into it, its terms moved over.
+fn normalize_rule_sets(tx: &Transaction) -> rusqlite::Result<()> {
+    let sets = tx
+        .prepare("SELECT id, rules FROM rule_sets ORDER BY id")?
+        .query_map([], |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    // Rows already in set form keep their ids, so rewriting the others can't collide with one
+    let (mut canonical, mut rewrite) = (HashMap::new(), vec![]);
+    for (id, rules) in sets {
+        let set = rule_set(&rules).unwrap_or_default();
+        if set == rules {
+            canonical.insert(set, id);
+        } else {
+            rewrite.push((id, set));
+        }
+    }
+    for (id, set) in rewrite {
+        match canonical.get(&set) {
+            Some(&into) => {
+                tx.execute("UPDATE terms SET rules_id = ?1 WHERE rules_id = ?2", params![into, id])?;
+                tx.execute("DELETE FROM rule_sets WHERE id = ?1", params![id])?;
+            }
+            None => {
+                tx.execute("UPDATE rule_sets SET rules = ?1 WHERE id = ?2", params![set, id])?;
+                canonical.insert(set, id);
+            }
+        }
+    }
+    Ok(())
+}
 
+/// Add `terms.term_norm` and fill it in for headwords `fold_latin` changes;
+/// it stays NULL for the rest, which lookups match on `term` itself.
+fn fold_terms(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column(tx, "terms", "term_norm", "TEXT")?;
+    let mut select = tx.prepare("SELECT id, term FROM terms")?;
+    let mut update = tx.prepare("UPDATE terms SET term_norm = ?2 WHERE id = ?1")?;
+    let mut rows = select.query([])?;
+    while let Some(r) = rows.next()? {
+        let id: i64 = r.get(0)?;
+        let term: String = r.get(1)?;
+        if let Cow::Owned(folded) = fold_latin(&term) {
+            update.execute(params![id, folded])?;
+        }
+    }
+    Ok(())
+}
+
+/// Fill in `glossaries.content_type`, and its counterpart on terms holding
+/// their own glossary, from the stored glossaries.
+fn tag_content_types(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column(tx, "glossaries", "content_type", "TEXT NOT NULL DEFAULT 'text'")?;
+    add_column(tx, "terms", "glossary_content_type", "TEXT")?;
+    for (select, update) in [
+        ("SELECT id, content FROM glossaries", "UPDATE glossaries SET content_type = ?2 WHERE id = ?1"),
+        ("SELECT id, glossary FROM terms WHERE glossary IS NOT NULL", "UPDATE terms SET glossary_content_type = ?2 WHERE id = ?1"),
+    ] {
+        let mut select = tx.prepare(select)?;
+        let mut update = tx.prepare(update)?;
+        let mut rows = select.query([])?;
+        while let Some(r) = rows.next()? {
+            let id: i64 = r.get(0)?;
+            let content: String = r.get(1)?;
+            let content_type = glossary_content_type(&serde_json::from_str(&content).unwrap_or(Value::Null));
+            update.execute(params![id, content_type])?;
+        }
+    }
+    Ok(())
+}
+
+/// Rebuild `terms` with `glossary_id` nullable, which `ALTER TABLE` can't
+/// do, and the `glossary` column holding a glossary that wasn't interned.
+/// Dropping the old table empties `term_tag_link` by cascade and takes the
+/// indexes with it, so both are made again. `term_norm`, filled in by a later
+/// step, is made here already since `create_indexes` covers it.
+fn inline_glossary_column(tx: &Transaction) -> rusqlite::Result<()> {
+    if tx.prepare("SELECT 1 FROM pragma_table_info('terms') WHERE name = 'glossary'")?.exists([])? {
+        return Ok(());
+    }
+    tx.execute_batch(
+        "CREATE TABLE terms_new (
+            id INTEGER PRIMARY KEY,
+            dict_id INTEGER NOT NULL REFERENCES dictionaries(id) ON DELETE CASCADE,
+            term TEXT NOT NULL,
+            term_norm TEXT,
+            reading TEXT NOT NULL,
+            def_tags_id INTEGER,
+            rules_id INTEGER,
+            score INTEGER NOT NULL DEFAULT 0,
+            glossary_id INTEGER REFERENCES glossaries(id),
+            glossary TEXT,
+            sequence INTEGER,
+            term_tags_id INTEGER
+        );
+        INSERT INTO terms_new (id, dict_id, term, reading, def_tags_id, rules_id, score, glossary_id, sequence, term_tags_id)
+            SELECT id, dict_id, term, reading, def_tags_id, rules_id, score, glossary_id, sequence, term_tags_id FROM terms;
+        DROP TABLE terms;
+        ALTER TABLE terms_new RENAME TO terms;",
+    )?;
+    tags::link_term_tags(tx, None)?;
+    create_indexes(tx)
+}
+
+fn normalize_terms(tx: &Transaction) -> rusqlite::Result<()> {
+    for table in ["terms", "term_meta"] {
+        let mut select = tx.prepare(&format!("SELECT id, term, reading FROM {table}"))?;
+        let mut update = tx.prepare(&format!("UPDATE {table} SET term = ?2, reading = ?3 WHERE id = ?1"))?;
+        let mut rows = select.query([])?;
+        while let Some(r) = rows.next()? {
+            let id: i64 = r.get(0)?;
+            // `term_meta.reading` may be NULL
+            let (term, reading): (String, Option<String>) = (r.get(1)?, r.get(2)?);
+            let n_term = normalize(&term);
+            let n_reading = reading.as_deref().map(normalize);
+            if n_term != term.as_str() || n_reading.as_deref() != reading.as_deref() {
+                update.execute(params![id, n_term, n_reading])?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn rebuild_glossary_fts(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute("DELETE FROM glossary_fts", [])?;
+    let mut select = tx.prepare("SELECT id, content FROM glossaries")?;
+    let mut insert = tx.prepare("INSERT INTO glossary_fts (rowid, text) VALUES (?1, ?2)")?;
+    let mut rows = select.query([])?;
+    while let Some(r) = rows.next()? {
+        let id: i64 = r.get(0)?;
+        let content: String = r.get(1)?;
+        let text = glossary_text(&serde_json::from_str(&content).unwrap_or(Value::String(content)));
+        insert.execute(params![id, text])?;
+    }
+    // Glossaries stored on term rows, from v14 on, under the rowids `INLINE_FTS_OFFSET` gives them
+    if !tx.prepare("SELECT 1 FROM pragma_table_info('terms') WHERE name = 'glossary'")?.exists([])? {
+        return Ok(());
+    }
+    let mut select = tx.prepare("SELECT id, glossary FROM terms WHERE glossary IS NOT NULL ORDER BY id")?;
+    let mut rows = select.query([])?;
+    while let Some(r) = rows.next()? {
+        let id: i64 = r.get(0)?;
+        let content: String = r.get(1)?;
+        let text = glossary_text(&serde_json::from_str(&content).unwrap_or(Value::String(content)));
+        insert.execute(params![id - INLINE_FTS_OFFSET, text])?;
+    }
+    Ok(())
+}
+
+/// `ALTER TABLE ... ADD COLUMN` unless the column is already there, which it
+/// is for databases `create_schema` made fresh.
+fn add_column(tx: &Transaction, table: &str, column: &str, decl: &str) -> rusqlite::Result<()> {
+    let exists = tx
+        .prepare("SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2")?
+        .exists(params![table, column])?;
+    if !exists {
+        tx.execute_batch(&format!("ALTER TABLE {table} ADD COLUMN {column} {decl}"))?;
+    }
+    Ok(())
+}
+
+/// Apply every migration newer than the stored `schema_version`, each in its
+/// own transaction together with its version bump.
+fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    let current: i64 = conn.query_row("SELECT value FROM schema_meta WHERE key = 'schema_version'", [], |r| {
+        Ok(r.get::<_, String>(0)?.parse().unwrap_or(1))
+    })?;
+
+    for (i, step) in MIGRATIONS.iter().enumerate() {
+        let to = i as i64 + 2;
+        if to <= current {
+            continue;
+        }
+        let tx = conn.unchecked_transaction()?;
+        step(&tx)?;
+        tx.execute("UPDATE schema_meta SET value = ?1 WHERE key = 'schema_version'", params![to.to_string()])?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Lookup indexes. Built after bulk insert rather than in `create_schema` so
+/// imports into a fresh database don't pay for index maintenance per row.
+pub fn create_indexes(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_terms_term ON terms(term);
+        CREATE INDEX IF NOT EXISTS idx_terms_term_norm ON terms(term_norm) WHERE term_norm IS NOT NULL;
+        CREATE INDEX IF NOT EXISTS idx_terms_reading ON terms(reading);
+        CREATE INDEX IF NOT EXISTS idx_terms_dict_sequence ON terms(dict_id, sequence);
+        CREATE INDEX IF NOT EXISTS idx_terms_glossary ON terms(glossary_id);
+        CREATE INDEX IF NOT EXISTS idx_term_tag_link_tag ON term_tag_link(tag_name);
+        CREATE INDEX IF NOT EXISTS idx_term_meta_term ON term_meta(term);
+        CREATE INDEX IF NOT EXISTS idx_term_meta_dict_sequence ON term_meta(dict_id, sequence);
+        CREATE INDEX IF NOT EXISTS idx_kanji_kanji ON kanji(kanji);
+        CREATE INDEX IF NOT EXISTS idx_kanji_meta_character ON kanji_meta(character);
+        ",
+    )
+}
+
+/// Per-connection SQLite settings for `open_db`.
+pub struct DbOptions {
+    /// Page cache size in KiB.
+    pub cache_size_kib: i64,
+    /// `journal_mode` to switch the database to, or `None` to leave it as is.
+    /// A mode stored with `set_journal_mode` takes precedence.
+    pub journal_mode: Option<&'static str>,
+    /// Open with `query_only = ON` and skip schema setup, which would write.
+    pub query_only: bool,
+}
+
+impl Default for DbOptions {
+    fn default() -> Self {
+        DbOptions {
+            cache_size_kib: if cfg!(mobile) { 8 * 1024 } else { 64 * 1024 },
+            journal_mode: Some("WAL"),
+            query_only: false,
+        }
+    }
+}
+
+impl DbOptions {
+    /// A second connection alongside the writer: reads only, and leaves the
+    /// journal mode the writer set.
+    pub fn query_only() -> Self {
+        DbOptions {
+            journal_mode: None,
+            query_only: true,
+            ..DbOptions::default()
+        }
+    }
+}
+
+/// Open the dictionary database at `db_path`, creating the schema if needed
+/// unless `options.query_only`.
+pub fn open_db(db_path: &Path, options: &DbOptions) -> rusqlite::Result<Connection> {
+    let conn = Connection::open(db_path)?;
+    // Connection settings, not stored in the file (bar journal_mode), so every
+    // connection has to set them itself; `foreign_keys` drives uninstall's cascades.
+    conn.execute_batch(&format!(
+        "PRAGMA foreign_keys = ON;
+        PRAGMA synchronous  = NORMAL;
+        PRAGMA cache_size   = -{};
+        PRAGMA temp_store   = MEMORY;
+        PRAGMA query_only   = {};",
+        options.cache_size_kib,
+        if options.query_only { "ON" } else { "OFF" },
+    ))?;
+    let mut journal_mode = options.journal_mode.map(str::to_string);
+    if !options.query_only {
+        create_schema(&conn)?;
+        discard_unfinished(&conn, true)?;
+        let stored = conn
+            .query_row("SELECT value FROM schema_meta WHERE key = 'journal_mode'", [], |r| r.get(0))
+            .optional()?;
+        journal_mode = stored.or(journal_mode);
+    }
+    if let Some(mode) = journal_mode {
+        conn.pragma_update(None, "journal_mode", mode)?;
+    }
+    Ok(conn)
+}
+
+/// Journal modes `set_journal_mode` accepts. `DELETE` and `TRUNCATE` leave no
+/// `-wal` and `-shm` files beside the database between transactions, so it is
+/// safe to keep in a folder synced by Dropbox or iCloud; in exchange readers
+/// wait out each commit.
+pub const JOURNAL_MODES: &[&str] = &["WAL", "DELETE", "TRUNCATE"];
+
+/// Store the journal mode `open_db` switches the database to from now on.
+/// Takes effect the next time the database is opened: leaving WAL needs the
+/// only connection to it.
+pub fn set_journal_mode(conn: &Connection, mode: &str) -> anyhow::Result<()> {
+    let mode = mode.to_ascii_uppercase();
+    if !JOURNAL_MODES.contains(&mode.as_str()) {
+        anyhow::bail!("unsupported journal mode {:?}, expected one of {:?}", mode, JOURNAL_MODES);
+    }
+    conn.execute(
+        "INSERT INTO schema_meta (key, value) VALUES ('journal_mode', ?1)
+         ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+        params![mode],
+    )?;
     Ok(())
 }
 
 fn intern(
-    tx: &Transaction,
-    insert_sql: &str,
-    select_sql: &str,
+    insert: &mut Statement,
+    select: &mut Statement,
     cache: &mut HashMap<String, i64>,
     value: &str,
 ) -> rusqlite::Result<i64> {
     if let Some(&v) = cache.get(value) {
         return Ok(v);
     }
-    tx.execute(insert_sql, params![value])?;
-    let id: i64 = tx.query_row(select_sql, params![value], |r| r.get(0))?;
+    insert.execute(params![value])?;
+    let id: i64 = select.query_row(params![value], |r| r.get(0))?;
     cache.insert(value.to_string(), id);
     Ok(id)
 }
 
-pub fn import_bundled_zips(db_path: &Path, resources_dir: &Path) -> anyhow::Result<()> {
-    // Prefer a `yomitan` subdirectory inside the provided resources dir
-    let search_dir = {
-        let cand = resources_dir.join("yomitan");
-        if cand.exists() && cand.is_dir() {
-            cand
-        } else {
-            resources_dir.to_path_buf()
-        }
-    };
+/// Ids of rows in the tables every dictionary shares (glossaries and tag and
+/// rule sets), kept across the dictionaries of a batch. Nothing interned is
+/// dictionary-scoped, so the cache only goes stale when rows it names are
+/// rolled back or garbage-collected.
+#[derive(Default)]
+struct InternCache {
+    glossaries: HashMap<String, i64>,
+    def_tags: HashMap<String, i64>,
+    term_tags: HashMap<String, i64>,
+    rules: HashMap<String, i64>,
+}
 
-    let mut zips = vec![];
-    for entry in std::fs::read_dir(&search_dir).with_context(|| format!("reading resources dir {}", search_dir.display()))? {
-        let e = entry?;
-        let p = e.path();
-        if p.extension().and_then(|s| s.to_str()) == Some("zip") {
-            zips.push(p);
+impl InternCache {
+    fn clear(&mut self) {
+        *self = InternCache::default();
+    }
+}
+
+/// Where a dictionary's `index.json` and bank files are read from. `Send`
+/// because term banks are read on a thread of their own during import.
+pub trait BankSource: Send {
+    /// Open the file `name` at the dictionary root, or `None` if there is no such file.
+    fn open(&mut self, name: &str) -> Option<Box<dyn Read + '_>>;
+}
+
+/// A packaged `.zip` dictionary, as distributed. Files are resolved against
+/// the folder holding `index.json`, which some exports nest below the root.
+pub struct ZipSource {
+    archive: ZipArchive<File>,
+    base: String,
+}
+
+impl ZipSource {
+    pub fn new(path: &Path) -> Result<Self, ImportError> {
+        let f = File::open(path).map_err(|source| ImportError::OpenFailed { path: path.to_path_buf(), source })?;
+        let archive = ZipArchive::new(f).map_err(|source| ImportError::NotAZip { path: path.to_path_buf(), source })?;
+        let base = archive
+            .file_names()
+            .filter_map(|n| n.strip_suffix("index.json"))
+            .filter(|prefix| prefix.is_empty() || prefix.ends_with('/'))
+            .min_by_key(|prefix| prefix.len())
+            .unwrap_or("")
+            .to_string();
+        Ok(ZipSource { archive, base })
+    }
+}
+
+impl BankSource for ZipSource {
+    fn open(&mut self, name: &str) -> Option<Box<dyn Read + '_>> {
+        let name = format!("{}{}", self.base, name);
+        self.archive.by_name(&name).ok().map(|f| Box::new(f) as Box<dyn Read>)
+    }
+}
+
+/// A dictionary extracted to a directory.
+pub struct DirSource(pub PathBuf);
+
+impl BankSource for DirSource {
+    fn open(&mut self, name: &str) -> Option<Box<dyn Read + '_>> {
+        File::open(self.0.join(name)).ok().map(|f| Box::new(f) as Box<dyn Read>)
+    }
+}
+
+/// `glossary_fts` indexes a glossary stored on its term row under the term's
+/// id less this. Those rowids are negative, apart from interned glossaries'
+/// ids, yet ascend as terms are inserted, which FTS5 needs to buffer inserts
+/// rather than flush each one.
+pub const INLINE_FTS_OFFSET: i64 = 1 << 62;
+
+/// A glossary in storage form: canonical JSON, its hash, its search text,
+/// how it renders and the media files it shows.
+pub struct GlossaryRow {
+    pub json: String,
+    /// `None` for one stored on its term row instead of interned.
+    pub hash: Option<String>,
+    pub text: String,
+    /// `text`, `structured` or `image`; see `glossary_content_type`.
+    pub content_type: &'static str,
+    pub media: Vec<String>,
+}
+
+impl GlossaryRow {
+    /// Canonicalize a raw glossary value, so entries with the same content
+    /// share one `glossaries` row wherever they came from. The hash that
+    /// finds that row is left out unless `intern`.
+    pub fn new(raw: Value, intern: bool) -> serde_json::Result<Self> {
+        let canonical = Glossary::parse(raw).into_canonical();
+        let json = serde_json::to_string(&canonical)?;
+        Ok(GlossaryRow {
+            hash: intern.then(|| sha1_hex(&json)),
+            json,
+            text: glossary_text(&canonical),
+            content_type: glossary_content_type(&canonical),
+            media: media_paths(&canonical),
+        })
+    }
+}
+
+/// A term bank entry, parsed and hashed off the writer thread.
+struct TermRow {
+    term: String,
+    reading: String,
+    def_tags: Option<String>,
+    rules: Option<String>,
+    score: i64,
+    glossary: GlossaryRow,
+    sequence: Option<i64>,
+    term_tags: Option<String>,
+}
+
+/// A rules field as the set it stands for: its tokens sorted and deduplicated,
+/// so `"vt v5"` and `"v5 vt"` intern as one `rule_sets` row. `None` if empty.
+fn rule_set(rules: &str) -> Option<String> {
+    let tokens: BTreeSet<&str> = rules.split_whitespace().collect();
+    (!tokens.is_empty()).then(|| tokens.into_iter().collect::<Vec<_>>().join(" "))
+}
+
+/// Whether `e[i]` is a string.
+fn str_at(e: &Value, i: usize) -> bool {
+    e.get(i).is_some_and(Value::is_string)
+}
+
+/// Whether `e[i]` is a string, null or missing.
+fn opt_str_at(e: &Value, i: usize) -> bool {
+    e.get(i).is_none_or(|v| v.is_string() || v.is_null())
+}
+
+/// Log the count of malformed entries skipped in bank `name`.
+fn note_skipped(title: &str, name: &str, skipped: usize) {
+    if skipped > 0 {
+        eprintln!("{}: skipped {} malformed entries in {}", title, skipped, name);
+    }
+}
+
+impl TermRow {
+    /// `None` for an entry without the term bank shape, which is skipped
+    /// rather than failing the import.
+    fn parse(e: &Value, format_version: Option<i64>, intern: bool) -> serde_json::Result<Option<Self>> {
+        let shaped = e.is_array()
+            && str_at(e, 0)
+            && str_at(e, 1)
+            && opt_str_at(e, 2)
+            && opt_str_at(e, 3)
+            && e.get(4).is_none_or(|v| v.is_number() || v.is_null())
+            && (format_version == Some(1) || opt_str_at(e, 7));
+        if !shaped {
+            return Ok(None);
         }
+
+        let tag_list = |v: Option<&Value>| v.and_then(Value::as_str).map(|s| s.trim()).filter(|s| !s.is_empty()).map(|s| s.to_string());
+        // v1: `[term, reading, defTags, rules, score, ...glossary]`
+        // v3: `[term, reading, defTags, rules, score, glossary, sequence, termTags]`
+        let (glossary_raw, sequence, term_tags) = if format_version == Some(1) {
+            let rest = e.as_array().map(|a| a.iter().skip(5).cloned().collect()).unwrap_or_default();
+            (Value::Array(rest), None, None)
+        } else {
+            (e.get(5).cloned().unwrap_or(Value::Null), e.get(6).and_then(Value::as_i64), tag_list(e.get(7)))
+        };
+        Ok(Some(TermRow {
+            term: normalize(e.get(0).and_then(Value::as_str).unwrap_or("")).into_owned(),
+            reading: normalize(e.get(1).and_then(Value::as_str).unwrap_or("")).into_owned(),
+            def_tags: tag_list(e.get(2)),
+            rules: e.get(3).and_then(Value::as_str).and_then(rule_set),
+            score: e.get(4).and_then(Value::as_i64).unwrap_or(0),
+            glossary: GlossaryRow::new(glossary_raw, intern)?,
+            sequence,
+            term_tags,
+        }))
     }
+}
 
-    if zips.is_empty() {
-        return Ok(());
+/// Up to a batch of consecutive term bank rows, parsed.
+struct ParsedBatch {
+    name: String,
+    rows: Vec<TermRow>,
+    skipped: usize,
+    /// Whether this batch ends its bank.
+    last: bool,
+}
+
+/// Rows of one bank file a default import parses and commits at a time.
+pub const DEFAULT_BATCH_ROWS: usize = 50_000;
+
+/// Commits an import every `rows` rows it writes, so a bank of any size never
+/// puts more than that in one transaction, or in the WAL between checkpoints.
+/// Committing makes the rows visible to other connections, so the dictionary
+/// is imported disabled and recorded under `schema_meta` `unfinished_import`
+/// until it lands, for `discard_unfinished` to delete should it not. `done`
+/// is saved to `import_progress` with each commit, for a rerun to resume from.
+struct Batches<'c> {
+    conn: &'c Connection,
+    dict_id: i64,
+    rows: usize,
+    pending: usize,
+    done: Done,
+}
+
+/// What an import has committed: the first `banks` bank files in load order,
+/// then `entries` entries of the next, holding the counted rows.
+#[derive(Clone, Copy, Default)]
+struct Done {
+    banks: usize,
+    entries: usize,
+    terms: usize,
+    term_meta: usize,
+    tags: usize,
+    kanji: usize,
+    skipped: usize,
+}
+
+impl Batches<'_> {
+    fn wrote(&mut self, rows: usize) -> rusqlite::Result<()> {
+        self.pending += rows;
+        if self.pending >= self.rows {
+            self.conn.execute(
+                "INSERT OR REPLACE INTO schema_meta (key, value) VALUES ('unfinished_import', ?1)",
+                params![self.dict_id],
+            )?;
+            let d = self.done;
+            self.conn.execute(
+                "UPDATE import_progress SET banks = ?2, entries = ?3, terms = ?4, term_meta = ?5, tags = ?6, kanji = ?7, skipped = ?8
+                 WHERE dict_id = ?1",
+                params![
+                    self.dict_id,
+                    d.banks as i64,
+                    d.entries as i64,
+                    d.terms as i64,
+                    d.term_meta as i64,
+                    d.tags as i64,
+                    d.kanji as i64,
+                    d.skipped as i64,
+                ],
+            )?;
+            self.conn.execute_batch("COMMIT; BEGIN;")?;
+            self.pending = 0;
+        }
+        Ok(())
     }
+}
 
-    let mut conn = Connection::open(db_path).with_context(|| format!("opening db {}", db_path.display()))?;
-    create_schema(&conn)?;
+/// Delete the dictionary an import committed batches of but failed, was
+/// cancelled or was cut short by a crash before finishing, if there is one.
+/// With `keep_resumable`, as on opening the database, one that has its
+/// `import_progress` is kept for importing it again to resume.
+fn discard_unfinished(conn: &Connection, keep_resumable: bool) -> rusqlite::Result<()> {
+    let unfinished: Option<i64> = conn
+        .query_row("SELECT CAST(value AS INTEGER) FROM schema_meta WHERE key = 'unfinished_import'", [], |r| r.get(0))
+        .optional()?;
+    let Some(id) = unfinished else { return Ok(()) };
+    let tx = conn.unchecked_transaction()?;
+    let resumable = keep_resumable && tx.prepare("SELECT 1 FROM import_progress WHERE dict_id = ?1")?.exists(params![id])?;
+    if !resumable {
+        tx.execute("DELETE FROM dictionaries WHERE id = ?1", params![id])?;
+        dictionaries::collect_garbage(&tx)?;
+    }
+    tx.execute("DELETE FROM schema_meta WHERE key = 'unfinished_import'", [])?;
+    tx.commit()
+}
 
-    for zip_path in zips {
-        let f = File::open(&zip_path).with_context(|| format!("opening zip {}", zip_path.display()))?;
-        let mut archive = ZipArchive::new(f).with_context(|| "reading zip archive")?;
+/// Run `f` on each entry of bank `name`, the `bank`th in load order, that
+/// `batches` hasn't committed, counting those it turns down as skipped. A
+/// bank committed whole is read only for the content hash.
+fn load_entries<R: Read>(
+    batches: &mut Batches,
+    bank: usize,
+    title: &str,
+    name: &str,
+    mut reader: R,
+    mut f: impl FnMut(&mut Batches, Value) -> Result<bool, ImportError>,
+) -> Result<(), ImportError> {
+    if bank < batches.done.banks {
+        return std::io::copy(&mut reader, &mut std::io::sink())
+            .map(drop)
+            .map_err(|e| ImportError::bank(name, serde_json::Error::io(e)));
+    }
+    let mut resumed = batches.done.entries;
+    let mut skipped = 0;
+    for_each_entry(name, reader, |e| {
+        if resumed > 0 {
+            resumed -= 1;
+            return Ok(());
+        }
+        batches.done.entries += 1;
+        if !f(batches, e)? {
+            skipped += 1;
+            batches.done.skipped += 1;
+        }
+        Ok(())
+    })?;
+    note_skipped(title, name, skipped);
+    batches.done.banks += 1;
+    batches.done.entries = 0;
+    Ok(())
+}
 
-        let index_file = match archive.by_name("index.json") {
-            Ok(mut f) => {
-                let mut s = String::new();
-                f.read_to_string(&mut s)?;
-                serde_json::from_str::<Value>(&s)?
-            }
-            Err(_) => continue,
+/// Read the term banks of `source` in order, feeding their bytes to `hasher`,
+/// and hand `sink` their entries parsed a batch of `batch_rows` at a time.
+/// Banks and entries `resume` says were committed are read for the hash only.
+/// Parses on the rayon pool if `parallel`. Stops at the first error, `sink`'s
+/// included.
+#[allow(clippy::too_many_arguments)]
+fn read_term_banks(
+    source: &mut dyn BankSource,
+    hasher: &mut Sha1,
+    resume: Done,
+    batch_rows: usize,
+    format_version: Option<i64>,
+    intern_glossaries: bool,
+    parallel: bool,
+    mut sink: impl FnMut(ParsedBatch) -> Result<(), ImportError>,
+) -> Result<(), ImportError> {
+    let parse = |name: &str, entries: Vec<Value>| -> Result<(Vec<TermRow>, usize), ImportError> {
+        let parse_one = |e: &Value| TermRow::parse(e, format_version, intern_glossaries);
+        let parsed = if parallel {
+            entries.par_iter().map(parse_one).collect::<serde_json::Result<Vec<_>>>()
+        } else {
+            entries.iter().map(parse_one).collect::<serde_json::Result<Vec<_>>>()
+        }
+        .map_err(|e| ImportError::bank(name, e))?;
+        let skipped = parsed.iter().filter(|row| row.is_none()).count();
+        Ok((parsed.into_iter().flatten().collect(), skipped))
+    };
+    for bank_i in 1.. {
+        let name = format!("term_bank_{}.json", bank_i);
+        let Some(f) = source.open(&name) else { break };
+        let mut reader = HashingReader { inner: f, hasher: &mut *hasher };
+        // Term banks load first, so `bank_i - 1` is the bank's place in load order
+        let (rows, skipped) = if bank_i - 1 < resume.banks {
+            // Committed whole by an import a crash cut short: read for the hash alone
+            std::io::copy(&mut reader, &mut std::io::sink()).map_err(|e| ImportError::bank(&name, serde_json::Error::io(e)))?;
+            (vec![], 0)
+        } else {
+            let mut entries = Vec::new();
+            let mut resumed = if bank_i - 1 == resume.banks { resume.entries } else { 0 };
+            for_each_entry(&name, reader, |e| {
+                if resumed > 0 {
+                    resumed -= 1;
+                    return Ok(());
+                }
+                entries.push(e);
+                if entries.len() == batch_rows {
+                    let (rows, skipped) = parse(&name, std::mem::take(&mut entries))?;
+                    sink(ParsedBatch { name: name.clone(), rows, skipped, last: false })?;
+                }
+                Ok(())
+            })?;
+            parse(&name, entries)?
         };
+        sink(ParsedBatch { name, rows, skipped, last: true })?;
+    }
+    Ok(())
+}
 
-        let title = index_file.get("title").and_then(Value::as_str).unwrap_or("").to_string();
-        let revision = index_file.get("revision").and_then(Value::as_str).unwrap_or("").to_string();
+/// What `import_banks` loaded.
+struct BankTotals {
+    terms: usize,
+    term_meta: usize,
+    tags: usize,
+    kanji: usize,
+    skipped: usize,
+    /// SHA1 over the bytes of every bank, in load order.
+    content_hash: String,
+}
 
-        // Skip if already installed
-        let exists: bool = conn.prepare("SELECT 1 FROM dictionaries WHERE title = ?1 AND revision = ?2")?
-            .exists(params![title, revision])?;
-        if exists {
-            continue;
+/// Load every bank file of `source` into the dictionary `batches.dict_id`,
+/// committing as `batches` says. `format_version` is the index.json `format`,
+/// which decides the term entry layout. Entries of the wrong shape are skipped
+/// and counted; unparseable bank files fail the import. Returns `None` if
+/// `cancel` was set, for the caller to roll back.
+#[allow(clippy::too_many_arguments)]
+fn import_banks(
+    conn: &Connection,
+    source: &mut dyn BankSource,
+    batches: &mut Batches,
+    format_version: Option<i64>,
+    intern_glossaries: bool,
+    title: &str,
+    cancel: &CancellationToken,
+    cache: &mut InternCache,
+    on_progress: &mut impl FnMut(Progress),
+) -> Result<Option<BankTotals>, ImportError> {
+    let mut insert_glossary = conn.prepare("INSERT OR IGNORE INTO glossaries (hash, content, content_type) VALUES (?1, ?2, ?3)")?;
+    let mut select_glossary = conn.prepare("SELECT id FROM glossaries WHERE hash = ?1")?;
+    let mut insert_glossary_fts = conn.prepare("INSERT INTO glossary_fts (rowid, text) VALUES (?1, ?2)")?;
+    let mut insert_def = conn.prepare("INSERT OR IGNORE INTO def_tag_sets (tags) VALUES (?1)")?;
+    let mut select_def = conn.prepare("SELECT id FROM def_tag_sets WHERE tags = ?1")?;
+    let mut insert_term_tags = conn.prepare("INSERT OR IGNORE INTO term_tag_sets (tags) VALUES (?1)")?;
+    let mut select_term_tags = conn.prepare("SELECT id FROM term_tag_sets WHERE tags = ?1")?;
+    let mut insert_rules = conn.prepare("INSERT OR IGNORE INTO rule_sets (rules) VALUES (?1)")?;
+    let mut select_rules = conn.prepare("SELECT id FROM rule_sets WHERE rules = ?1")?;
+    let mut insert_term = conn.prepare("INSERT INTO terms (dict_id, term, term_norm, reading, def_tags_id, rules_id, score, glossary_id, glossary, glossary_content_type, sequence, term_tags_id) VALUES (?1,?2,NULLIF(?3,?2),?4,?5,?6,?7,?8,?9,?10,?11,?12)")?;
+    let mut insert_meta = conn.prepare("INSERT INTO term_meta (dict_id, term, mode, reading, data) VALUES (?1,?2,?3,?4,?5)")?;
+    let mut upsert_tag = conn.prepare(
+        "INSERT INTO tags (dict_id, name, category, sort_order, notes, score) VALUES (?1,?2,?3,?4,?5,?6)
+         ON CONFLICT (dict_id, name) DO UPDATE SET
+            category = excluded.category, sort_order = excluded.sort_order, notes = excluded.notes, score = excluded.score",
+    )?;
+    let mut select_tag = conn.prepare("SELECT 1 FROM tags WHERE dict_id = ?1 AND name = ?2")?;
+    let mut insert_kanji_meta = conn.prepare("INSERT INTO kanji_meta (dict_id, character, mode, data) VALUES (?1,?2,?3,?4)")?;
+    let mut insert_kanji = conn.prepare("INSERT INTO kanji (dict_id, kanji, onyomi, kunyomi, tags, meanings, stats) VALUES (?1,?2,?3,?4,?5,?6,?7)")?;
+    let mut insert_media = conn.prepare("INSERT OR IGNORE INTO media (dict_id, path) VALUES (?1, ?2)")?;
+
+    let (dict_id, batch_rows) = (batches.dict_id, batches.rows);
+    let mut content_hasher = Sha1::new();
+    let mut entries = 0;
+    let mut banks = 0;
+    let mut media = HashSet::new();
+
+    // term banks: read a batch of entries at a time and inserted here in bank
+    // order since SQLite takes one writer
+    let resume = batches.done;
+    let mut bank_skipped = 0;
+    let mut insert_batch = |batch: ParsedBatch| -> Result<(), ImportError> {
+        let ParsedBatch { name, rows, skipped: batch_skipped, last } = batch;
+        entries += batch_skipped;
+        bank_skipped += batch_skipped;
+        let written = rows.len();
+        for row in rows {
+            entries += 1;
+            let glossary = row.glossary;
+            let (glossary_id, inline) = match glossary.hash {
+                Some(hash) => match cache.glossaries.get(&hash) {
+                    Some(&id) => (Some(id), None),
+                    None => {
+                        let inserted = insert_glossary.execute(params![hash, glossary.json, glossary.content_type])?;
+                        let id: i64 = select_glossary.query_row(params![hash], |r| r.get(0))?;
+                        if inserted > 0 {
+                            insert_glossary_fts.execute(params![id, glossary.text])?;
+                        }
+                        cache.glossaries.insert(hash, id);
+                        (Some(id), None)
+                    }
+                },
+                None => (None, Some(glossary.json)),
+            };
+
+            let def_id = match row.def_tags.as_deref() {
+                Some(s) => Some(intern(&mut insert_def, &mut select_def, &mut cache.def_tags, s)?),
+                None => None,
+            };
+            let rules_id = match row.rules.as_deref() {
+                Some(s) => Some(intern(&mut insert_rules, &mut select_rules, &mut cache.rules, s)?),
+                None => None,
+            };
+            let term_tags_id = match row.term_tags.as_deref() {
+                Some(s) => Some(intern(&mut insert_term_tags, &mut select_term_tags, &mut cache.term_tags, s)?),
+                None => None,
+            };
+
+            let inline_type = inline.is_some().then_some(glossary.content_type);
+            insert_term.execute(params![dict_id, row.term, fold_latin(&row.term), row.reading, def_id, rules_id, row.score, glossary_id, inline, inline_type, row.sequence, term_tags_id])?;
+            if inline.is_some() {
+                insert_glossary_fts.execute(params![conn.last_insert_rowid() - INLINE_FTS_OFFSET, glossary.text])?;
+            }
+            // Recorded without data for `load_media` to read once the banks are in
+            for path in glossary.media {
+                if !media.contains(&path) {
+                    insert_media.execute(params![dict_id, path])?;
+                    media.insert(path);
+                }
+            }
+            batches.done.terms += 1;
         }
+        // A bank committed whole before comes as one empty batch
+        if banks == batches.done.banks {
+            batches.done.entries += written + batch_skipped;
+            batches.done.skipped += batch_skipped;
+            if last {
+                batches.done.banks += 1;
+                batches.done.entries = 0;
+            }
+        }
+        batches.wrote(written)?;
+        if last {
+            note_skipped(title, &name, bank_skipped);
+            bank_skipped = 0;
+            banks += 1;
+        }
+        on_progress(Progress {
+            title: title.to_string(),
+            bank: banks,
+            entries,
+        });
+        Ok(())
+    };
+    // The stop error only ends the read: the caller checks `cancel` before it
+    let stopped = |name: &str| ImportError::bank(name, de::Error::custom("import stopped"));
+    let read = if rayon::current_num_threads() > 1 {
+        // A reader thread parses the next batch on the rayon pool while this one inserts
+        std::thread::scope(|scope| -> Result<(), ImportError> {
+            // One batch queued while one is parsed and one inserted
+            let (send, recv) = mpsc::sync_channel::<Result<ParsedBatch, ImportError>>(1);
+            let (source, hasher) = (&mut *source, &mut content_hasher);
+            scope.spawn(move || {
+                let read = read_term_banks(source, hasher, resume, batch_rows, format_version, intern_glossaries, true, |batch| {
+                    let name = batch.name.clone();
+                    // A closed channel means the writer already gave up
+                    send.send(Ok(batch)).map_err(|_| stopped(&name))
+                });
+                if let Err(e) = read {
+                    let _ = send.send(Err(e));
+                }
+            });
+            for batch in recv {
+                insert_batch(batch?)?;
+                // Dropping `recv` on the way out stops the reader at its next send
+                if cancel.is_cancelled() {
+                    break;
+                }
+            }
+            Ok(())
+        })
+    } else {
+        // One thread has no parallelism to pay for a handoff, so parse and insert in turn
+        read_term_banks(&mut *source, &mut content_hasher, resume, batch_rows, format_version, intern_glossaries, false, |batch| {
+            let name = batch.name.clone();
+            insert_batch(batch)?;
+            if cancel.is_cancelled() {
+                return Err(stopped(&name));
+            }
+            Ok(())
+        })
+    };
+    if cancel.is_cancelled() {
+        return Ok(None);
+    }
+    read?;
 
-        let tx = conn.transaction()?;
-        tx.execute(
-            "INSERT INTO dictionaries (title, revision, author, url, description, is_bundled) VALUES (?1, ?2, ?3, ?4, ?5, 1)",
-            params![
-                index_file.get("title").and_then(Value::as_str),
-                index_file.get("revision").and_then(Value::as_str),
-                index_file.get("author").and_then(Value::as_str),
-                index_file.get("url").and_then(Value::as_str),
-                index_file.get("description").and_then(Value::as_str),
-            ],
-        )?;
-
-        let dict_id: i64 = tx.query_row("SELECT last_insert_rowid()", [], |r| r.get(0))?;
-
-        // prepare statements
-        let insert_glossary = "INSERT OR IGNORE INTO glossaries (hash, content) VALUES (?1, ?2)";
-        let select_glossary = "SELECT id FROM glossaries WHERE hash = ?1";
-        let insert_def = "INSERT OR IGNORE INTO def_tag_sets (tags) VALUES (?1)";
-        let select_def = "SELECT id FROM def_tag_sets WHERE tags = ?1";
-        let insert_term_tags = "INSERT OR IGNORE INTO term_tag_sets (tags) VALUES (?1)";
-        let select_term_tags = "SELECT id FROM term_tag_sets WHERE tags = ?1";
-        let insert_rules = "INSERT OR IGNORE INTO rule_sets (rules) VALUES (?1)";
-        let select_rules = "SELECT id FROM rule_sets WHERE rules = ?1";
-        let insert_term = "INSERT INTO terms (dict_id, term, reading, def_tags_id, rules_id, score, glossary_id, sequence, term_tags_id) VALUES (?1,?2,?3,?4,?5,?6,?7,?8,?9)";
-        let insert_meta = "INSERT INTO term_meta (dict_id, term, mode, reading, data) VALUES (?1,?2,?3,?4,?5)";
-        let insert_tag = "INSERT OR IGNORE INTO tags (dict_id, name, category, sort_order, notes, score) VALUES (?1,?2,?3,?4,?5,?6)";
-
-        let mut glossary_cache: HashMap<String, i64> = HashMap::new();
-        let mut def_cache: HashMap<String, i64> = HashMap::new();
-        let mut term_tags_cache: HashMap<String, i64> = HashMap::new();
-        let mut rules_cache: HashMap<String, i64> = HashMap::new();
-
-        // term banks
-        let mut bank_i = 1;
-        loop {
-            let name = format!("term_bank_{}.json", bank_i);
-            match archive.by_name(&name) {
-                Ok(mut f) => {
-                    let mut s = String::new();
-                    f.read_to_string(&mut s)?;
-                    let entries: Vec<Value> = serde_json::from_str(&s)?;
-                    for e in entries {
-                        let term = e.get(0).and_then(Value::as_str).unwrap_or("");
-                        let reading = e.get(1).and_then(Value::as_str).unwrap_or("");
-                        let def_tags = e.get(2).and_then(Value::as_str).map(|s| s.trim()).filter(|s| !s.is_empty()).map(|s| s.to_string());
-                        let rules = e.get(3).and_then(Value::as_str).map(|s| s.trim()).filter(|s| !s.is_empty()).map(|s| s.to_string());
-                        let score = e.get(4).and_then(Value::as_i64).unwrap_or(0);
-                        let glossary_val = e.get(5).cloned().unwrap_or(Value::Null);
-                        let sequence = e.get(6).and_then(Value::as_i64);
-                        let term_tags = e.get(7).and_then(Value::as_str).map(|s| s.trim()).filter(|s| !s.is_empty()).map(|s| s.to_string());
-
-                        let glossary_json = serde_json::to_string(&glossary_val)?;
-                        let hash = sha1_hex(&glossary_json);
-                        let glossary_id = if let Some(&id) = glossary_cache.get(&hash) {
-                            id
-                        } else {
-                            tx.execute(insert_glossary, params![hash, glossary_json])?;
-                            let id: i64 = tx.query_row(select_glossary, params![hash], |r| r.get(0))?;
-                            glossary_cache.insert(hash.clone(), id);
-                            id
-                        };
-
-                        let def_id = if let Some(s) = def_tags.as_deref() {
-                            intern(&tx, insert_def, select_def, &mut def_cache, s)?
-                        } else { 0 };
-                        let rules_id = if let Some(s) = rules.as_deref() {
-                            intern(&tx, insert_rules, select_rules, &mut rules_cache, s)?
-                        } else { 0 };
-                        let term_tags_id = if let Some(s) = term_tags.as_deref() {
-                            intern(&tx, insert_term_tags, select_term_tags, &mut term_tags_cache, s)?
-                        } else { 0 };
-
-                        tx.execute(
-                            insert_term,
-                            params![
-                                dict_id,
-                                term,
-                                reading,
-                                if def_id != 0 { Some(def_id) } else { Option::<i64>::None },
-                                if rules_id != 0 { Some(rules_id) } else { Option::<i64>::None },
-                                score,
-                                glossary_id,
-                                sequence,
-                                if term_tags_id != 0 { Some(term_tags_id) } else { Option::<i64>::None },
-                            ],
-                        )?;
+    // term_meta banks
+    let mut meta_i = 1;
+    loop {
+        let name = format!("term_meta_bank_{}.json", meta_i);
+        match source.open(&name) {
+            Some(f) => {
+                load_entries(batches, banks, title, &name, HashingReader { inner: f, hasher: &mut content_hasher }, |batches, e| {
+                    entries += 1;
+                    if !(str_at(&e, 0) && str_at(&e, 1)) {
+                        return Ok(false);
                     }
-                    bank_i += 1;
-                    continue;
+                    let term = normalize(e.get(0).and_then(Value::as_str).unwrap_or(""));
+                    let mode = e.get(1).and_then(Value::as_str).unwrap_or("");
+                    let data = e.get(2).cloned().unwrap_or(Value::Null);
+                    // A frequency no lookup could read, like `{reading}` alone, is no entry
+                    if mode == "freq" && term_meta::parse_freq(&data).is_none() {
+                        return Ok(false);
+                    }
+                    let reading = term_meta::meta_reading(mode, &data).map(|s| normalize(s).into_owned());
+                    insert_meta.execute(params![dict_id, term, mode, reading, data.to_string()])?;
+                    batches.done.term_meta += 1;
+                    batches.wrote(1)?;
+                    Ok(true)
+                })?;
+                banks += 1;
+                on_progress(Progress {
+                    title: title.to_string(),
+                    bank: banks,
+                    entries,
+                });
+                if cancel.is_cancelled() {
+                    return Ok(None);
                 }
-                Err(_) => break,
+                meta_i += 1;
+                continue;
             }
+            None => break,
         }
+    }
 
-        // term_meta banks
-        let mut meta_i = 1;
-        loop {
-            let name = format!("term_meta_bank_{}.json", meta_i);
-            match archive.by_name(&name) {
-                Ok(mut f) => {
-                    let mut s = String::new();
-                    f.read_to_string(&mut s)?;
-                    let entries: Vec<Value> = serde_json::from_str(&s)?;
-                    for e in entries {
-                        let term = e.get(0).and_then(Value::as_str).unwrap_or("");
-                        let mode = e.get(1).and_then(Value::as_str).unwrap_or("");
-                        let data = e.get(2).cloned().unwrap_or(Value::Null);
-                        let reading = data.get("reading").and_then(Value::as_str).map(|s| s.to_string());
-                        tx.execute(insert_meta, params![dict_id, term, mode, reading, serde_json::to_string(&data)?])?;
+    // tag banks
+    let mut tag_i = 1;
+    loop {
+        let name = format!("tag_bank_{}.json", tag_i);
+        match source.open(&name) {
+            Some(f) => {
+                load_entries(batches, banks, title, &name, HashingReader { inner: f, hasher: &mut content_hasher }, |batches, e| {
+                    entries += 1;
+                    if !(str_at(&e, 0) && opt_str_at(&e, 1) && opt_str_at(&e, 3)) {
+                        return Ok(false);
                     }
-                    meta_i += 1;
-                    continue;
+                    let name = e.get(0).and_then(Value::as_str).unwrap_or("");
+                    let category = e.get(1).and_then(Value::as_str);
+                    let sort_order = e.get(2).and_then(Value::as_i64).unwrap_or(0);
+                    let notes = e.get(3).and_then(Value::as_str);
+                    let tag_score = e.get(4).and_then(Value::as_i64).unwrap_or(0);
+                    // A name defined again replaces what was stored, but still counts once
+                    if !select_tag.exists(params![dict_id, name])? {
+                        batches.done.tags += 1;
+                    }
+                    upsert_tag.execute(params![dict_id, name, category, sort_order, notes, tag_score])?;
+                    batches.wrote(1)?;
+                    Ok(true)
+                })?;
+                banks += 1;
+                on_progress(Progress {
+                    title: title.to_string(),
+                    bank: banks,
+                    entries,
+                });
+                if cancel.is_cancelled() {
+                    return Ok(None);
                 }
-                Err(_) => break,
+                tag_i += 1;
+                continue;
             }
+            None => break,
         }
+    }
 
-        // tag banks
-        let mut tag_i = 1;
-        loop {
-            let name = format!("tag_bank_{}.json", tag_i);
-            match archive.by_name(&name) {
-                Ok(mut f) => {
-                    let mut s = String::new();
-                    f.read_to_string(&mut s)?;
-                    let entries: Vec<Value> = serde_json::from_str(&s)?;
-                    for e in entries {
-                        let name = e.get(0).and_then(Value::as_str).unwrap_or("");
-                        let category = e.get(1).and_then(Value::as_str);
-                        let sort_order = e.get(2).and_then(Value::as_i64).unwrap_or(0);
-                        let notes = e.get(3).and_then(Value::as_str);
-                        let tag_score = e.get(4).and_then(Value::as_i64).unwrap_or(0);
-                        tx.execute(insert_tag, params![dict_id, name, category, sort_order, notes, tag_score])?;
+    // kanji banks
+    let mut kanji_i = 1;
+    loop {
+        let name = format!("kanji_bank_{}.json", kanji_i);
+        match source.open(&name) {
+            Some(f) => {
+                load_entries(batches, banks, title, &name, HashingReader { inner: f, hasher: &mut content_hasher }, |batches, e| {
+                    entries += 1;
+                    if !(str_at(&e, 0) && opt_str_at(&e, 1) && opt_str_at(&e, 2) && opt_str_at(&e, 3)) {
+                        return Ok(false);
                     }
-                    tag_i += 1;
-                    continue;
+                    let character = e.get(0).and_then(Value::as_str).unwrap_or("");
+                    let onyomi = e.get(1).and_then(Value::as_str);
+                    let kunyomi = e.get(2).and_then(Value::as_str);
+                    let tags = e.get(3).and_then(Value::as_str);
+                    let meanings = e.get(4).filter(|v| v.is_array()).cloned().unwrap_or_else(|| Value::Array(vec![]));
+                    let stats = e.get(5).filter(|v| v.is_object()).cloned().unwrap_or_else(|| Value::Object(Default::default()));
+                    insert_kanji.execute(params![
+                        dict_id,
+                        character,
+                        onyomi,
+                        kunyomi,
+                        tags,
+                        meanings.to_string(),
+                        stats.to_string(),
+                    ])?;
+                    batches.done.kanji += 1;
+                    batches.wrote(1)?;
+                    Ok(true)
+                })?;
+                banks += 1;
+                on_progress(Progress {
+                    title: title.to_string(),
+                    bank: banks,
+                    entries,
+                });
+                if cancel.is_cancelled() {
+                    return Ok(None);
                 }
-                Err(_) => break,
+                kanji_i += 1;
+                continue;
             }
+            None => break,
         }
+    }
 
+    // kanji_meta banks
+    let mut kanji_meta_i = 1;
+    loop {
+        let name = format!("kanji_meta_bank_{}.json", kanji_meta_i);
+        match source.open(&name) {
+            Some(f) => {
+                load_entries(batches, banks, title, &name, HashingReader { inner: f, hasher: &mut content_hasher }, |batches, e| {
+                    entries += 1;
+                    if !(str_at(&e, 0) && str_at(&e, 1)) {
+                        return Ok(false);
+                    }
+                    let character = e.get(0).and_then(Value::as_str).unwrap_or("");
+                    let mode = e.get(1).and_then(Value::as_str).unwrap_or("");
+                    let data = e.get(2).cloned().unwrap_or(Value::Null);
+                    insert_kanji_meta.execute(params![dict_id, character, mode, data.to_string()])?;
+                    batches.wrote(1)?;
+                    Ok(true)
+                })?;
+                banks += 1;
+                on_progress(Progress {
+                    title: title.to_string(),
+                    bank: banks,
+                    entries,
+                });
+                if cancel.is_cancelled() {
+                    return Ok(None);
+                }
+                kanji_meta_i += 1;
+                continue;
+            }
+            None => break,
+        }
+    }
+
+    Ok(Some(BankTotals {
+        terms: batches.done.terms,
+        term_meta: batches.done.term_meta,
+        tags: batches.done.tags,
+        kanji: batches.done.kanji,
+        skipped: batches.done.skipped,
+        content_hash: digest_hex(&content_hasher),
+    }))
+}
+
+/// Read the files the glossaries of dictionary `batches.dict_id` show from
+/// `source` into their `media` rows, committing as `batches` says. A path
+/// leading out of the dictionary, or to a file it doesn't have, is dropped.
+/// Returns how many files the dictionary has stored, or `None` if `cancel`
+/// was set.
+fn load_media(
+    batches: &mut Batches,
+    source: &mut dyn BankSource,
+    title: &str,
+    cancel: &CancellationToken,
+) -> Result<Option<usize>, ImportError> {
+    let (conn, dict_id) = (batches.conn, batches.dict_id);
+    // Files read before a crash cut the import short are already in
+    let paths = conn
+        .prepare("SELECT path FROM media WHERE dict_id = ?1 AND data IS NULL")?
+        .query_map(params![dict_id], |r| r.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    let mut update = conn.prepare("UPDATE media SET data = ?3 WHERE dict_id = ?1 AND path = ?2")?;
+    let mut delete = conn.prepare("DELETE FROM media WHERE dict_id = ?1 AND path = ?2")?;
+    let mut missing = 0;
+    for path in paths {
+        let inside = Path::new(&path).components().all(|c| matches!(c, Component::Normal(_)));
+        let file = if inside { source.open(&path) } else { None };
+        match file {
+            Some(mut f) => {
+                let mut data = Vec::new();
+                f.read_to_end(&mut data).map_err(|e| ImportError::bank(&path, serde_json::Error::io(e)))?;
+                update.execute(params![dict_id, path, data])?;
+                batches.wrote(1)?;
+            }
+            None => {
+                delete.execute(params![dict_id, path])?;
+                missing += 1;
+            }
+        }
+        if cancel.is_cancelled() {
+            return Ok(None);
+        }
+    }
+    if missing > 0 {
+        eprintln!("{}: dropped {} media files the dictionary doesn't have", title, missing);
+    }
+    let stored: i64 = conn.query_row("SELECT COUNT(*) FROM media WHERE dict_id = ?1", params![dict_id], |r| r.get(0))?;
+    Ok(Some(stored as usize))
+}
+
+/// Import a single Yomitan dictionary into `conn`, either a zip or a directory
+/// it was extracted to. Fails with `ImportError::MissingIndex` when there is
+/// no `index.json` and so it isn't a dictionary. `on_progress` is called after
+/// each batch of term bank rows and each other bank file is loaded.
+///
+/// The import commits every `batch_rows` rows (see `Batches`), yet lands as a
+/// whole: a failure in any bank deletes what was committed rather than leaving
+/// partial terms behind. Setting `cancel` does the same and returns an
+/// `Aborted` summary. What a crash cuts short is kept instead, out of sight:
+/// importing the same title and revision again resumes after its last batch.
+///
+/// Without `intern_glossaries`, each term keeps its glossary on its own row
+/// and no glossary is hashed. That is quicker for dictionaries whose
+/// glossaries are nearly all distinct, such as example sentences, which
+/// sharing would save little on.
+#[allow(clippy::too_many_arguments)]
+pub fn import_one(
+    conn: &Connection,
+    path: &Path,
+    is_bundled: bool,
+    on_conflict: OnConflict,
+    batch_rows: usize,
+    intern_glossaries: bool,
+    cancel: &CancellationToken,
+    mut on_progress: impl FnMut(Progress),
+) -> Result<ImportSummary, ImportError> {
+    import_batched(conn, path, is_bundled, on_conflict, batch_rows, intern_glossaries, cancel, &mut InternCache::default(), &mut on_progress)
+}
+
+/// Import several dictionaries one after another as `import_one` does,
+/// sharing one `InternCache`. A dictionary that fails, is cancelled or turns
+/// out a duplicate rolls back only itself. A path that isn't a dictionary is
+/// passed over with a warning. On an error, the ones imported before it
+/// stay. Cancelling stops the batch.
+#[allow(clippy::too_many_arguments)]
+pub fn import_many(
+    conn: &Connection,
+    paths: &[PathBuf],
+    is_bundled: bool,
+    on_conflict: OnConflict,
+    batch_rows: usize,
+    intern_glossaries: bool,
+    cancel: &CancellationToken,
+    mut on_progress: impl FnMut(Progress),
+) -> Result<Vec<ImportSummary>, ImportError> {
+    let mut cache = InternCache::default();
+    let mut summaries = vec![];
+    for path in paths {
+        let result = import_batched(conn, path, is_bundled, on_conflict, batch_rows, intern_glossaries, cancel, &mut cache, &mut on_progress);
+        if !matches!(&result, Ok(s) if s.action == ImportAction::Imported) {
+            cache.clear();
+        }
+        let summary = match result {
+            Ok(summary) => summary,
+            Err(e @ (ImportError::MissingIndex { .. } | ImportError::NotYomitan { .. })) => {
+                eprintln!("skipped {}", e);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let aborted = summary.action == ImportAction::Aborted;
+        summaries.push(summary);
+        if aborted {
+            break;
+        }
+    }
+    Ok(summaries)
+}
+
+/// Why `index` isn't shaped like a Yomitan index.json, if it isn't: that is
+/// an object with string `title` and `revision` and an integer `format`, or
+/// `version` in older dictionaries.
+fn not_yomitan_index(index: &Value) -> Option<String> {
+    let Some(index) = index.as_object() else {
+        return Some("is not a JSON object".to_string());
+    };
+    if let Some(key) = ["title", "revision"].into_iter().find(|k| !index.get(*k).is_some_and(Value::is_string)) {
+        return Some(format!("has no {} string", key));
+    }
+    if !["format", "version"].iter().any(|k| index.get(*k).and_then(Value::as_i64).is_some()) {
+        return Some("has no format or version number".to_string());
+    }
+    None
+}
+
+/// `import_into` under a transaction of its own, committed for good if the
+/// dictionary lands. Otherwise the open batch is rolled back and any
+/// committed ones deleted.
+#[allow(clippy::too_many_arguments)]
+fn import_batched(
+    conn: &Connection,
+    path: &Path,
+    is_bundled: bool,
+    on_conflict: OnConflict,
+    batch_rows: usize,
+    intern_glossaries: bool,
+    cancel: &CancellationToken,
+    cache: &mut InternCache,
+    on_progress: &mut impl FnMut(Progress),
+) -> Result<ImportSummary, ImportError> {
+    let tx = conn.unchecked_transaction()?;
+    let result = import_into(&tx, path, is_bundled, on_conflict, batch_rows, intern_glossaries, cancel, cache, on_progress);
+    if matches!(&result, Ok(s) if s.landed()) {
         tx.commit()?;
+    } else {
+        drop(tx);
+        discard_unfinished(conn, false)?;
+    }
+    result
+}
+
+/// One dictionary's import into `conn`, which is inside a transaction the
+/// caller commits only if the summary `landed`.
+#[allow(clippy::too_many_arguments)]
+fn import_into(
+    conn: &Connection,
+    path: &Path,
+    is_bundled: bool,
+    on_conflict: OnConflict,
+    batch_rows: usize,
+    intern_glossaries: bool,
+    cancel: &CancellationToken,
+    cache: &mut InternCache,
+    on_progress: &mut impl FnMut(Progress),
+) -> Result<ImportSummary, ImportError> {
+    let mut source: Box<dyn BankSource> = if path.is_dir() {
+        Box::new(DirSource(path.to_path_buf()))
+    } else {
+        Box::new(ZipSource::new(path)?)
+    };
+
+    let invalid_index = |reason: String| ImportError::InvalidIndex {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let index_file = match source.open("index.json") {
+        Some(mut f) => {
+            let mut s = String::new();
+            f.read_to_string(&mut s).map_err(|e| invalid_index(format!("could not be read: {}", e)))?;
+            serde_json::from_str::<Value>(s.strip_prefix('\u{FEFF}').unwrap_or(&s))
+                .map_err(|e| invalid_index(format!("is not valid JSON: {}", e)))?
+        }
+        None => return Err(ImportError::MissingIndex { path: path.to_path_buf() }),
+    };
+
+    if let Some(reason) = not_yomitan_index(&index_file) {
+        return Err(ImportError::NotYomitan { path: path.to_path_buf(), reason });
+    }
+    let required = |key: &str| -> Result<String, ImportError> {
+        match index_file.get(key).and_then(Value::as_str).map(str::trim) {
+            Some(s) if !s.is_empty() => Ok(s.to_string()),
+            _ => Err(invalid_index(format!("has no {}", key))),
+        }
+    };
+    let title = required("title")?;
+    let revision = required("revision")?;
+    let format_version = index_file.get("format").or_else(|| index_file.get("version")).and_then(Value::as_i64);
+    let sequenced = index_file.get("sequenced").and_then(Value::as_bool).unwrap_or(false);
+    // Yomitan treats frequencies as ranks unless told they are occurrence counts
+    let frequency_mode = match index_file.get("frequencyMode").and_then(Value::as_str) {
+        Some("occurrence-based") => "occurrence-based",
+        _ => "rank-based",
+    };
+    let is_updatable = index_file.get("isUpdatable").and_then(Value::as_bool).unwrap_or(false);
+
+    let installed = dictionaries::installed(conn, &title)?;
+    if installed.iter().any(|(_, rev)| *rev == revision) {
+        let duplicate_of = Some(title.clone());
+        return Ok(ImportSummary::skipped(title, revision, duplicate_of));
     }
+    let replace: Vec<i64> = match on_conflict {
+        OnConflict::KeepBoth => vec![],
+        OnConflict::Skip if installed.is_empty() => vec![],
+        OnConflict::Skip => return Ok(ImportSummary::skipped(title, revision, None)),
+        OnConflict::ReplaceOlder => {
+            if installed.iter().any(|(_, rev)| compare_revisions(rev, &revision).is_gt()) {
+                return Ok(ImportSummary::skipped(title, revision, None));
+            }
+            installed.iter().map(|(id, _)| *id).collect()
+        }
+    };
 
-    conn.execute_batch("VACUUM;")?;
+    // This revision's import cut short by a crash, which `installed` leaves
+    // out: it goes on from what that committed
+    let resumed = conn
+        .query_row(
+            "SELECT p.dict_id, p.banks, p.entries, p.terms, p.term_meta, p.tags, p.kanji, p.skipped
+             FROM import_progress p JOIN dictionaries d ON d.id = p.dict_id
+             WHERE d.title = ?1 AND d.revision = ?2",
+            params![title, revision],
+            |r| {
+                let count = |i| r.get::<_, i64>(i).map(|n| n as usize);
+                Ok((
+                    r.get::<_, i64>(0)?,
+                    Done {
+                        banks: count(1)?,
+                        entries: count(2)?,
+                        terms: count(3)?,
+                        term_meta: count(4)?,
+                        tags: count(5)?,
+                        kanji: count(6)?,
+                        skipped: count(7)?,
+                    },
+                ))
+            },
+        )
+        .optional()?;
+    let (dict_id, done) = match resumed {
+        Some(resumed) => resumed,
+        None => {
+            conn.execute(
+                "INSERT INTO dictionaries (title, revision, author, url, description, is_bundled, format_version, sequenced, frequency_mode, is_updatable, index_url, download_url, enabled)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, 0)",
+                params![
+                    title,
+                    revision,
+                    index_file.get("author").and_then(Value::as_str),
+                    index_file.get("url").and_then(Value::as_str),
+                    index_file.get("description").and_then(Value::as_str),
+                    is_bundled,
+                    format_version,
+                    sequenced,
+                    frequency_mode,
+                    is_updatable,
+                    index_file.get("indexUrl").and_then(Value::as_str),
+                    index_file.get("downloadUrl").and_then(Value::as_str),
+                ],
+            )?;
+            let dict_id: i64 = conn.query_row("SELECT last_insert_rowid()", [], |r| r.get(0))?;
+            conn.execute("INSERT INTO import_progress (dict_id) VALUES (?1)", params![dict_id])?;
+            (dict_id, Done::default())
+        }
+    };
+    if let Some(mut f) = source.open("styles.css") {
+        let mut css = String::new();
+        f.read_to_string(&mut css).map_err(|e| ImportError::bank("styles.css", serde_json::Error::io(e)))?;
+        let css = styles::scope_css(css.strip_prefix('\u{FEFF}').unwrap_or(&css), &styles::dictionary_scope(dict_id));
+        conn.execute("UPDATE dictionaries SET styles = ?1 WHERE id = ?2", params![css, dict_id])?;
+    }
 
+    let mut batches = Batches {
+        conn,
+        dict_id,
+        rows: batch_rows.max(1),
+        pending: 0,
+        done,
+    };
+    let Some(totals) = import_banks(conn, source.as_mut(), &mut batches, format_version, intern_glossaries, &title, cancel, cache, on_progress)? else {
+        return Ok(ImportSummary::aborted(title, revision));
+    };
+
+    // Same banks under another title or revision: the caller rolls this import back
+    let existing = conn
+        .prepare("SELECT id, title FROM dictionaries WHERE content_hash = ?1")?
+        .query_map(params![totals.content_hash], |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?
+        .into_iter()
+        .find(|(id, _)| !replace.contains(id))
+        .map(|(_, title)| title);
+    if existing.is_some() {
+        return Ok(ImportSummary::skipped(title, revision, existing));
+    }
+    let Some(media) = load_media(&mut batches, source.as_mut(), &title, cancel)? else {
+        return Ok(ImportSummary::aborted(title, revision));
+    };
+    conn.execute("UPDATE dictionaries SET content_hash = ?1 WHERE id = ?2", params![totals.content_hash, dict_id])?;
+    tags::link_term_tags(conn, Some(dict_id))?;
+    if sequenced {
+        term_meta::link_sequences(conn, dict_id)?;
+    }
+    // Old revisions go only once the new one is in, so a failed import leaves
+    // them installed; glossaries the two share were reused rather than re-inserted
+    for id in &replace {
+        conn.execute("DELETE FROM dictionaries WHERE id = ?1", params![id])?;
+    }
+    if !replace.is_empty() {
+        dictionaries::collect_garbage(conn)?;
+    }
+    conn.execute("UPDATE dictionaries SET enabled = 1 WHERE id = ?1", params![dict_id])?;
+    conn.execute("DELETE FROM import_progress WHERE dict_id = ?1", params![dict_id])?;
+    conn.execute("DELETE FROM schema_meta WHERE key = 'unfinished_import'", [])?;
+
+    Ok(ImportSummary {
+        title,
+        revision,
+        action: if replace.is_empty() { ImportAction::Imported } else { ImportAction::Replaced },
+        terms: totals.terms,
+        term_meta: totals.term_meta,
+        tags: totals.tags,
+        kanji: totals.kanji,
+        media,
+        skipped: totals.skipped,
+        was_duplicate: false,
+        duplicate_of: None,
+    })
+}
+
+/// Post-import housekeeping, run once after a batch of dictionaries lands.
+/// `ANALYZE` keeps planner stats current; `VACUUM` rewrites the whole file,
+/// so it only runs when asked for. Last, a `TRUNCATE` checkpoint folds the
+/// WAL, which a big import grows to the size of everything it wrote, back
+/// into the database and empties it. That needs every other connection
+/// idle, so callers hold the reader's lock across this.
+pub fn finish_import(conn: &Connection, vacuum: bool) -> rusqlite::Result<()> {
+    create_indexes(conn)?;
+    conn.execute_batch("ANALYZE;")?;
+    if vacuum {
+        conn.execute_batch("VACUUM;")?;
+    }
+    // A no-op outside WAL mode
+    let busy: i64 = conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |r| r.get(0))?;
+    if busy != 0 {
+        eprintln!("WAL checkpoint after import did not complete: another connection was reading");
+    }
     Ok(())
 }
 
-/// Helper to find a resources dir: prefer executable sibling `resources`, then project `src-tauri/resources`.
+/// The bundled zips in `resources_dir`, or in its `yomitan` subdirectory if it has one.
+fn bundled_zips(resources_dir: &Path) -> Result<Vec<PathBuf>, ImportError> {
+    // Prefer a `yomitan` subdirectory inside the provided resources dir
+    let search_dir = {
+        let cand = resources_dir.join("yomitan");
+        if cand.exists() && cand.is_dir() {
+            cand
+        } else {
+            resources_dir.to_path_buf()
+        }
+    };
+
+    let mut zips = vec![];
+    let open_failed = |source| ImportError::OpenFailed {
+        path: search_dir.clone(),
+        source,
+    };
+    for entry in std::fs::read_dir(&search_dir).map_err(open_failed)? {
+        let e = entry.map_err(open_failed)?;
+        let p = e.path();
+        if p.extension().and_then(|s| s.to_str()) == Some("zip") {
+            zips.push(p);
+        }
+    }
+    Ok(zips)
+}
+
+/// File name, size and modification time of a bundled zip. An app update
+/// shipping another file under the same name changes the latter two.
+fn zip_fingerprint(path: &Path) -> Result<(String, i64, i64), ImportError> {
+    let open_failed = |source| ImportError::OpenFailed {
+        path: path.to_path_buf(),
+        source,
+    };
+    let meta = std::fs::metadata(path).map_err(open_failed)?;
+    let modified = meta.modified().map_err(open_failed)?.duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    Ok((name, meta.len() as i64, modified))
+}
+
+fn record_bundled(conn: &Connection, fingerprints: &[(String, i64, i64)]) -> rusqlite::Result<()> {
+    let mut insert = conn.prepare("INSERT OR REPLACE INTO bundled_imports (file_name, size, modified) VALUES (?1, ?2, ?3)")?;
+    for (name, size, modified) in fingerprints {
+        insert.execute(params![name, size, modified])?;
+    }
+    Ok(())
+}
+
+/// Import the bundled zips that `bundled_imports` has no record of as they
+/// are now, and record them, so a launch with nothing new opens no archive.
+/// A bundled dictionary the user uninstalled stays recorded and so gone.
+/// `finish_import` is left to the caller.
+pub fn import_bundled_zips(
+    conn: &Connection,
+    resources_dir: &Path,
+    cancel: &CancellationToken,
+    mut on_progress: impl FnMut(Progress),
+) -> Result<Vec<ImportSummary>, ImportError> {
+    let mut recorded = conn.prepare("SELECT 1 FROM bundled_imports WHERE file_name = ?1 AND size = ?2 AND modified = ?3")?;
+    let mut zips = vec![];
+    let mut fingerprints = vec![];
+    for path in bundled_zips(resources_dir)? {
+        let fingerprint = zip_fingerprint(&path)?;
+        if !recorded.exists(params![fingerprint.0, fingerprint.1, fingerprint.2])? {
+            zips.push(path);
+            fingerprints.push(fingerprint);
+        }
+    }
+    if zips.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let summaries = import_many(conn, &zips, true, OnConflict::KeepBoth, DEFAULT_BATCH_ROWS, true, cancel, &mut on_progress)?;
+    // A cancelled batch is retried next time; those it imported are skipped then
+    if !summaries.iter().any(|s| s.action == ImportAction::Aborted) {
+        record_bundled(conn, &fingerprints)?;
+    }
+
+    Ok(summaries)
+}
+
+/// Record the bundled zips in `resources_dir` as imported without importing
+/// them, if `bundled_imports` is empty: a database made before it kept track
+/// had them imported on creation, and may have had some uninstalled since.
+pub fn adopt_bundled_zips(conn: &Connection, resources_dir: &Path) -> Result<(), ImportError> {
+    if conn.prepare("SELECT 1 FROM bundled_imports")?.exists([])? {
+        return Ok(());
+    }
+    let fingerprints = bundled_zips(resources_dir)?.iter().map(|p| zip_fingerprint(p)).collect::<Result<Vec<_>, _>>()?;
+    record_bundled(conn, &fingerprints)?;
+    Ok(())
+}
+
+/// Whether `dir` or its `yomitan` subdirectory holds a `.zip`, which is where
+/// `import_bundled_zips` looks.
+fn has_zips(dir: &Path) -> bool {
+    [dir.to_path_buf(), dir.join("yomitan")].iter().any(|d| {
+        std::fs::read_dir(d).is_ok_and(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .any(|e| e.path().extension().and_then(|s| s.to_str()) == Some("zip"))
+        })
+    })
+}
+
+/// Helper to find a resources dir: `CJDIC_RESOURCES_DIR` if set, then the
+/// executable sibling `resources`, then project `src-tauri/resources`. Only a
+/// directory with bundled zips counts, so an empty stray one doesn't shadow the next.
 pub fn find_resources_dir() -> Option<PathBuf> {
-    if let Ok(exe) = std::env::current_exe() {
-        if let Some(parent) = exe.parent() {
-            let cand = parent.join("resources");
-            if cand.exists() {
-                return Some(cand);
-            }
+    if let Some(dir) = std::env::var_os("CJDIC_RESOURCES_DIR") {
+        let cand = PathBuf::from(dir);
+        if has_zips(&cand) {
+            return Some(cand);
+        }
+    }
+    if let Ok(exe) = std::env::current_exe()
+        && let Some(parent) = exe.parent()
+    {
+        let cand = parent.join("resources");
+        if has_zips(&cand) {
+            return Some(cand);
         }
     }
     if let Ok(cwd) = std::env::current_dir() {
         let cand = cwd.join("src-tauri").join("resources");
-        if cand.exists() {
+        if has_zips(&cand) {
             return Some(cand);
         }
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("PRAGMA foreign_keys = ON;").unwrap();
+        create_schema(&conn).unwrap();
+        conn
+    }
+
+    fn count(conn: &Connection, table: &str) -> i64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |r| r.get(0)).unwrap()
+    }
+
+    /// An extracted dictionary with `index.json` and the given bank files.
+    fn write_dict(index: Value, banks: &[(&str, &[u8])]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.json"), index.to_string()).unwrap();
+        for (name, content) in banks {
+            std::fs::write(dir.path().join(name), content).unwrap();
+        }
+        dir
+    }
+
+    fn import(conn: &Connection, path: &Path, batch_rows: usize) -> Result<ImportSummary, ImportError> {
+        import_one(conn, path, false, OnConflict::ReplaceOlder, batch_rows, true, &CancellationToken::default(), |_| {})
+    }
+
+    #[test]
+    fn corrupt_later_bank_leaves_nothing() {
+        let conn = test_db();
+        let dict = write_dict(
+            serde_json::json!({ "title": "Corrupt", "revision": "1", "format": 3 }),
+            &[
+                ("term_bank_1.json", br#"[["a", "", "", "", 0, ["one"], 1, ""], ["b", "", "", "", 0, ["two"], 2, ""]]"#),
+                ("term_bank_2.json", br#"[["c", "", "", "", 0, ["three"], 3, "#),
+            ],
+        );
+        // One row per batch, so bank 1 is committed before bank 2 fails
+        let Err(err) = import(&conn, dict.path(), 1) else { panic!("import succeeded") };
+        assert!(matches!(err, ImportError::BankParse { .. }), "{err}");
+        for table in ["terms", "glossaries", "dictionaries", "import_progress"] {
+            assert_eq!(count(&conn, table), 0, "{table}");
+        }
+        let unfinished = conn.prepare("SELECT 1 FROM schema_meta WHERE key = 'unfinished_import'").unwrap().exists([]).unwrap();
+        assert!(!unfinished);
+    }
+
+    /// `terms` rows of dictionary `title` but for their ids and sequence
+    /// numbers, which format 1 has none of, with rules spelled out.
+    fn term_rows(conn: &Connection, title: &str) -> Vec<Vec<Option<String>>> {
+        let mut stmt = conn
+            .prepare(
+                "SELECT t.term, t.term_norm, t.reading, t.def_tags_id, rs.rules, t.score, t.glossary_id, t.glossary, t.glossary_content_type, t.term_tags_id
+                 FROM terms t JOIN dictionaries d ON d.id = t.dict_id LEFT JOIN rule_sets rs ON rs.id = t.rules_id
+                 WHERE d.title = ?1 ORDER BY t.term",
+            )
+            .unwrap();
+        stmt.query_map(params![title], |r| (0..10).map(|i| r.get(i).map(as_text)).collect())
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    fn as_text(v: rusqlite::types::Value) -> Option<String> {
+        match v {
+            rusqlite::types::Value::Null => None,
+            rusqlite::types::Value::Integer(n) => Some(n.to_string()),
+            rusqlite::types::Value::Text(s) => Some(s),
+            v => Some(format!("{v:?}")),
+        }
+    }
+
+    #[test]
+    fn nested_zip_layout_resolves_banks() {
+        use std::io::Write;
+        use zip::write::{FileOptions, ZipWriter};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested.zip");
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        let files = [
+            ("README.txt", "not part of the dictionary".to_string()),
+            ("dict/index.json", serde_json::json!({ "title": "Nested", "revision": "1", "format": 3 }).to_string()),
+            ("dict/term_bank_1.json", r#"[["猫", "ねこ", "", "", 0, ["cat"], 1, ""]]"#.to_string()),
+        ];
+        for (name, content) in files {
+            zip.start_file(name, FileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let conn = test_db();
+        let summary = import(&conn, &path, DEFAULT_BATCH_ROWS).unwrap();
+        assert_eq!((summary.title.as_str(), summary.terms), ("Nested", 1));
+        let term: String = conn.query_row("SELECT term FROM terms", [], |r| r.get(0)).unwrap();
+        assert_eq!(term, "猫");
+    }
+
+    #[test]
+    fn reader_searches_while_an_import_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("yomitan.db");
+        let writer = open_db(&db_path, &DbOptions::default()).unwrap();
+        let reader = open_db(&db_path, &DbOptions::query_only()).unwrap();
+        let first = write_dict(
+            serde_json::json!({ "title": "First", "revision": "1", "format": 3 }),
+            &[("term_bank_1.json", r#"[["猫", "ねこ", "", "", 0, ["cat"], 1, ""]]"#.as_bytes())],
+        );
+        import(&writer, first.path(), DEFAULT_BATCH_ROWS).unwrap();
+        let second = write_dict(
+            serde_json::json!({ "title": "Second", "revision": "1", "format": 3 }),
+            &[
+                ("term_bank_1.json", r#"[["猫", "ねこ", "", "", 0, ["feline"], 1, ""], ["犬", "いぬ", "", "", 0, ["dog"], 2, ""]]"#.as_bytes()),
+                ("term_bank_2.json", r#"[["鳥", "とり", "", "", 0, ["bird"], 3, ""]]"#.as_bytes()),
+            ],
+        );
+
+        // Progress is reported from inside the import's open write transaction
+        let mut searches = 0;
+        let summary = import_one(&writer, second.path(), false, OnConflict::ReplaceOlder, 1, true, &CancellationToken::default(), |_| {
+            assert!(!writer.is_autocommit(), "no write transaction open");
+            let hits = crate::lookup::search_prefix(&reader, "猫", 10, 0, false).unwrap();
+            let glossaries: Vec<_> = hits.entries.iter().map(|e| e.glossary.to_string()).collect();
+            assert_eq!(glossaries, [r#"["cat"]"#], "the unfinished import stays out of lookups");
+            assert!(crate::lookup::lookup_exact(&reader, "犬").unwrap().is_empty());
+            searches += 1;
+        })
+        .unwrap();
+        assert_eq!(summary.terms, 3);
+        assert!(searches > 0);
+        assert_eq!(crate::lookup::lookup_exact(&reader, "犬").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn bank_with_bom_parses_and_hashes_raw_bytes() {
+        let bank = r#"[["猫", "ねこ", "", "", 0, ["cat"], 1, ""]]"#;
+        let with_bom = [UTF8_BOM, bank.as_bytes()].concat();
+        let conn = test_db();
+        let dict = write_dict(serde_json::json!({ "title": "BOM", "revision": "1", "format": 3 }), &[("term_bank_1.json", &with_bom)]);
+        assert_eq!(import(&conn, dict.path(), DEFAULT_BATCH_ROWS).unwrap().terms, 1);
+
+        let hash: String = conn.query_row("SELECT content_hash FROM dictionaries", [], |r| r.get(0)).unwrap();
+        assert_eq!(hash, digest_hex(&Sha1::from(&with_bom)));
+        // The same entries without the BOM are other bytes, so not a duplicate
+        let plain = write_dict(serde_json::json!({ "title": "No BOM", "revision": "1", "format": 3 }), &[("term_bank_1.json", bank.as_bytes())]);
+        let summary = import(&conn, plain.path(), DEFAULT_BATCH_ROWS).unwrap();
+        assert!(summary.action == ImportAction::Imported && summary.terms == 1);
+    }
+
+    #[test]
+    fn format_1_and_3_banks_import_alike() {
+        let conn = test_db();
+        let v1 = write_dict(
+            serde_json::json!({ "title": "V1", "revision": "1", "version": 1 }),
+            &[("term_bank_1.json", r#"[["読む", "よむ", "v5", "v5 vt", 10, "to read", "to recite"], ["本", "ほん", "n", "", -5, "book"]]"#.as_bytes())],
+        );
+        let v3 = write_dict(
+            serde_json::json!({ "title": "V3", "revision": "1", "format": 3 }),
+            &[(
+                "term_bank_1.json",
+                r#"[["読む", "よむ", "v5", "v5 vt", 10, ["to read", "to recite"], 1, ""], ["本", "ほん", "n", "", -5, ["book"], 2, ""]]"#.as_bytes(),
+            )],
+        );
+        assert_eq!(import(&conn, v1.path(), DEFAULT_BATCH_ROWS).unwrap().terms, 2);
+        assert_eq!(import(&conn, v3.path(), DEFAULT_BATCH_ROWS).unwrap().terms, 2);
+
+        let rows = term_rows(&conn, "V1");
+        assert_eq!(rows, term_rows(&conn, "V3"));
+        let yomu = &rows[1];
+        assert_eq!(yomu[0].as_deref(), Some("読む"));
+        assert_eq!(yomu[4].as_deref(), Some("v5 vt"));
+        assert_eq!(yomu[5].as_deref(), Some("10"));
+        assert_eq!(rows[0][4], None);
+        assert_eq!(rows[0][5].as_deref(), Some("-5"));
+    }
+
+    #[test]
+    fn reordered_rules_intern_as_one_set() {
+        let conn = test_db();
+        let dict = write_dict(
+            serde_json::json!({ "title": "Rules", "revision": "1", "format": 3 }),
+            &[("term_bank_1.json", r#"[["読む", "よむ", "", "v5 vt", 0, ["to read"], 1, ""], ["書く", "かく", "", "vt  v5", 0, ["to write"], 2, ""]]"#.as_bytes())],
+        );
+        import(&conn, dict.path(), DEFAULT_BATCH_ROWS).unwrap();
+        let ids: i64 = conn.query_row("SELECT COUNT(DISTINCT rules_id) FROM terms", [], |r| r.get(0)).unwrap();
+        assert_eq!(ids, 1);
+        let rules: String = conn.query_row("SELECT rules FROM rule_sets", [], |r| r.get(0)).unwrap();
+        assert_eq!(rules, "v5 vt");
+    }
+
+    #[test]
+    fn rule_set_migration_merges_equal_rows() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO dictionaries (id, title, revision) VALUES (1, 'Old', '1');
+             INSERT INTO rule_sets (id, rules) VALUES (1, 'vt v5'), (2, 'v5 vt'), (3, 'vi  v1');
+             INSERT INTO terms (dict_id, term, reading, rules_id) VALUES (1, '読む', 'よむ', 1), (1, '書く', 'かく', 2), (1, '見る', 'みる', 3);",
+        )
+        .unwrap();
+        let tx = conn.unchecked_transaction().unwrap();
+        normalize_rule_sets(&tx).unwrap();
+        tx.commit().unwrap();
+
+        let sets: Vec<(i64, String)> = conn
+            .prepare("SELECT id, rules FROM rule_sets ORDER BY id")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(sets, [(2, "v5 vt".to_string()), (3, "v1 vi".to_string())]);
+        let rules_ids: Vec<i64> = conn
+            .prepare("SELECT rules_id FROM terms ORDER BY id")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(rules_ids, [2, 2, 3]);
+    }
+
+    /// The terms and content hash of importing `dict` in 1 row batches on a
+    /// rayon pool of `threads`, which picks the serial or parallel parse.
+    fn import_on(threads: usize, dict: &Path) -> (Vec<Vec<Option<String>>>, String) {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        pool.install(|| {
+            let conn = test_db();
+            import(&conn, dict, 1).unwrap();
+            let hash = conn.query_row("SELECT content_hash FROM dictionaries", [], |r| r.get(0)).unwrap();
+            (term_rows(&conn, "Paths"), hash)
+        })
+    }
+
+    #[test]
+    fn serial_and_parallel_parse_import_alike() {
+        let dict = write_dict(
+            serde_json::json!({ "title": "Paths", "revision": "1", "format": 3 }),
+            &[
+                ("term_bank_1.json", r#"[["読む", "よむ", "v5", "v5 vt", 10, ["to read"], 1, ""], ["bad"], ["本", "ほん", "n", "", -5, ["book"], 2, ""]]"#.as_bytes()),
+                ("term_bank_2.json", r#"[["見る", "みる", "v1", "v1", 3, ["to see"], 3, ""]]"#.as_bytes()),
+            ],
+        );
+        let serial = import_on(1, dict.path());
+        assert_eq!(serial.0.len(), 3);
+        assert_eq!(import_on(2, dict.path()), serial);
+    }
+
+    #[test]
+    fn sha1_hex_is_lowercase_hex() {
+        assert_eq!(sha1_hex(""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(sha1_hex("abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(digest_hex(&Sha1::from("abc")), sha1_hex("abc"));
+    }
+}